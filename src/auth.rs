@@ -0,0 +1,301 @@
+use crate::metrics::ServerMetrics;
+use arc_swap::ArcSwap;
+use http::header::{InvalidHeaderValue, AUTHORIZATION};
+use http::{HeaderMap, HeaderValue, Request};
+use jsonrpsee::core::BoxError;
+use jsonrpsee::http_client::HttpResponse;
+use reth_rpc_layer::{
+    secret_to_bearer_header, AuthValidator, JwtAuthValidator, JwtError, JwtSecret,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use subtle::ConstantTimeEq;
+use tower::{Layer, Service, ServiceExt};
+
+/// A JWT secret shared by the clients of an upstream, which can be replaced while they run so a
+/// rotated secret is used without a restart.
+#[derive(Debug, Clone)]
+pub struct SharedSecret(Arc<ArcSwap<JwtSecret>>);
+
+impl SharedSecret {
+    pub fn new(secret: JwtSecret) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(secret)))
+    }
+
+    /// Returns the current secret.
+    pub fn load(&self) -> JwtSecret {
+        **self.0.load()
+    }
+
+    /// Replaces the secret for every request signed from now on.
+    pub fn store(&self, secret: JwtSecret) {
+        self.0.store(Arc::new(secret));
+    }
+
+    /// Returns an `Authorization` header value carrying a token signed with the current secret.
+    pub fn bearer_header(&self) -> HeaderValue {
+        secret_to_bearer_header(&self.load())
+    }
+}
+
+impl From<JwtSecret> for SharedSecret {
+    fn from(secret: JwtSecret) -> Self {
+        Self::new(secret)
+    }
+}
+
+/// Validates the JWT of every request against the current secret of a [SharedSecret], like
+/// [JwtAuthValidator] does with a fixed secret, so clients signing with a rotated secret are let
+/// through once it is reloaded.
+///
+/// Rejected requests are counted by `auth_failures_total` when metrics are enabled, among them
+/// those sending a static token other than the configured one, which is not a JWT.
+#[derive(Debug, Clone)]
+pub struct SharedJwtValidator {
+    secret: SharedSecret,
+    metrics: Option<Arc<ServerMetrics>>,
+}
+
+impl SharedJwtValidator {
+    pub fn new(secret: SharedSecret) -> Self {
+        Self {
+            secret,
+            metrics: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Option<Arc<ServerMetrics>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+}
+
+impl AuthValidator for SharedJwtValidator {
+    fn validate(&self, headers: &HeaderMap) -> Result<(), HttpResponse> {
+        let secret = self.secret.load();
+        let result = JwtAuthValidator::new(secret).validate(headers);
+        if let (Err(_), Some(metrics)) = (&result, &self.metrics) {
+            metrics.record_auth_failure(auth_failure_reason(&secret, headers));
+        }
+        result
+    }
+}
+
+/// Returns why the JWT of a request rejected by [JwtAuthValidator] is not valid.
+fn auth_failure_reason(secret: &JwtSecret, headers: &HeaderMap) -> &'static str {
+    let Some(authorization) = headers.get(AUTHORIZATION) else {
+        return "missing";
+    };
+    let token = authorization
+        .to_str()
+        .ok()
+        .and_then(|authorization| authorization.strip_prefix("Bearer "));
+    match token.map(|token| secret.validate(token)) {
+        Some(Err(JwtError::InvalidSignature)) => "invalid_signature",
+        Some(Err(JwtError::InvalidIssuanceTimestamp)) => "expired",
+        _ => "malformed",
+    }
+}
+
+/// Signs every request with a token from a [SharedSecret], like
+/// [reth_rpc_layer::AuthClientLayer] does with a fixed secret.
+#[derive(Debug, Clone)]
+pub struct AuthClientLayer {
+    secret: SharedSecret,
+}
+
+impl AuthClientLayer {
+    pub fn new(secret: SharedSecret) -> Self {
+        Self { secret }
+    }
+}
+
+impl<S> Layer<S> for AuthClientLayer {
+    type Service = AuthClientService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthClientService {
+            inner,
+            secret: self.secret.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthClientService<S> {
+    inner: S,
+    secret: SharedSecret,
+}
+
+impl<S, B> Service<Request<B>> for AuthClientService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, self.secret.bearer_header());
+        self.inner.call(request)
+    }
+}
+
+/// Marks a request let through by a [StaticTokenLayer] without a JWT, whose engine calls the
+/// proxy refuses.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticTokenAuth;
+
+/// Authenticates requests with the `auth` layer, except those sending the static token as a
+/// bearer token, for the tooling that cannot mint engine JWTs.
+///
+/// Requests with the static token are marked with [StaticTokenAuth] so the proxy refuses them
+/// the engine and admin methods. Without a static token, requests are passed on unauthenticated.
+#[derive(Debug, Clone)]
+pub struct StaticTokenLayer<L> {
+    authorization: Option<HeaderValue>,
+    auth: L,
+}
+
+impl<L> StaticTokenLayer<L> {
+    pub fn new(token: Option<&str>, auth: L) -> Result<Self, InvalidHeaderValue> {
+        let authorization = token
+            .map(|token| HeaderValue::try_from(format!("Bearer {token}")))
+            .transpose()?;
+        Ok(Self {
+            authorization,
+            auth,
+        })
+    }
+}
+
+impl<S: Clone, L: Layer<S>> Layer<S> for StaticTokenLayer<L> {
+    type Service = StaticTokenService<L::Service, S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StaticTokenService {
+            authenticated: self.auth.layer(inner.clone()),
+            inner,
+            authorization: self.authorization.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticTokenService<A, S> {
+    /// `inner` behind the auth layer
+    authenticated: A,
+    inner: S,
+    authorization: Option<HeaderValue>,
+}
+
+impl<A, S, B> Service<Request<B>> for StaticTokenService<A, S>
+where
+    A: Service<Request<B>, Response = S::Response> + Clone + Send + 'static,
+    A::Error: Into<BoxError>,
+    A::Future: Send,
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    /// Readiness is waited for on the service the request is sent to, once it is known.
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let Some(authorization) = &self.authorization else {
+            let inner = self.inner.clone();
+            return Box::pin(async move { inner.oneshot(request).await.map_err(Into::into) });
+        };
+
+        // Compared in constant time, so the token cannot be guessed from the response times
+        let authorized = request
+            .headers()
+            .get(AUTHORIZATION)
+            .is_some_and(|value| bool::from(value.as_bytes().ct_eq(authorization.as_bytes())));
+        if authorized {
+            request.extensions_mut().insert(StaticTokenAuth);
+            let inner = self.inner.clone();
+            Box::pin(async move { inner.oneshot(request).await.map_err(Into::into) })
+        } else {
+            let authenticated = self.authenticated.clone();
+            Box::pin(async move { authenticated.oneshot(request).await.map_err(Into::into) })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_rpc_layer::Claims;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_auth_failures_metric() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let secret = JwtSecret::random();
+        let validator = SharedJwtValidator::new(SharedSecret::new(secret))
+            .with_metrics(Some(Arc::new(ServerMetrics::default())));
+        let headers = |authorization: Option<HeaderValue>| {
+            let mut headers = HeaderMap::new();
+            if let Some(authorization) = authorization {
+                headers.insert(AUTHORIZATION, authorization);
+            }
+            headers
+        };
+        // Issued an hour ago, past the drift the validator allows
+        let issued_at =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - Duration::from_secs(3600);
+        let expired = secret
+            .encode(&Claims {
+                iat: issued_at.as_secs(),
+                exp: None,
+            })
+            .unwrap();
+
+        assert!(validator
+            .validate(&headers(Some(secret_to_bearer_header(&secret))))
+            .is_ok());
+        for authorization in [
+            None,
+            Some(HeaderValue::from_static("Bearer not-a-jwt")),
+            // A static token other than the configured one
+            Some(HeaderValue::from_static("Bearer static-token")),
+            Some(secret_to_bearer_header(&JwtSecret::random())),
+            Some(HeaderValue::try_from(format!("Bearer {expired}")).unwrap()),
+        ] {
+            assert!(validator.validate(&headers(authorization)).is_err());
+        }
+
+        let rendered = handle.render();
+        for (reason, count) in [
+            ("missing", 1),
+            ("malformed", 2),
+            ("invalid_signature", 1),
+            ("expired", 1),
+        ] {
+            let line = format!(r#"auth_failures_total{{reason="{reason}"}} {count}"#);
+            assert!(
+                rendered.lines().any(|rendered| rendered == line),
+                "{rendered}"
+            );
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::client::ExecutionClient;
+use crate::metrics::record_canary;
+use futures::future::join_all;
+use jsonrpsee::core::client::{ClientT, Error as ClientError};
+use jsonrpsee::rpc_params;
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tracing::warn;
+
+/// Engine methods announced by the canary calls
+const CAPABILITIES: [&str; 3] = [
+    "engine_forkchoiceUpdatedV3",
+    "engine_getPayloadV3",
+    "engine_newPayloadV3",
+];
+
+/// Calls `engine_exchangeCapabilities` on each of `upstreams` every `interval`, counting the
+/// outcome and recording the duration of the calls in the `canary_` metrics, so an upstream
+/// that degrades shows before a block is requested from it.
+pub async fn run_canary(upstreams: Vec<(&'static str, ExecutionClient)>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        join_all(
+            upstreams
+                .iter()
+                .map(|(upstream, client)| check(upstream, client)),
+        )
+        .await;
+    }
+}
+
+async fn check(upstream: &'static str, client: &ExecutionClient) {
+    let started_at = Instant::now();
+    let result = client
+        .auth_client
+        .request::<serde_json::Value, _>("engine_exchangeCapabilities", rpc_params![CAPABILITIES])
+        .await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(ClientError::RequestTimeout) => "timeout",
+        Err(_) => "error",
+    };
+    if let Err(e) = result {
+        warn!(message = "canary call failed", upstream, url = %client.auth_rpc, error = %e);
+    }
+    record_canary(upstream, outcome, started_at.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::server::Server;
+    use jsonrpsee::RpcModule;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use reth_rpc_layer::JwtSecret;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_canary_metrics() -> eyre::Result<()> {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let mut module = RpcModule::new(());
+        module.register_method("engine_exchangeCapabilities", |params, _, _| {
+            params.one::<Vec<String>>().unwrap_or_default()
+        })?;
+        let l2 = Server::builder().build("127.0.0.1:0").await?;
+        let l2_addr = l2.local_addr()?;
+        let l2 = l2.start(module);
+        // Nothing listens on the builder port
+        let builder_addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let client = |addr: SocketAddr| -> eyre::Result<ExecutionClient> {
+            let uri = format!("http://{addr}").parse()?;
+            Ok(ExecutionClient::new(uri, JwtSecret::random(), 100)?)
+        };
+        let upstreams = vec![("l2", client(l2_addr)?), ("builder", client(builder_addr)?)];
+
+        // The first calls are made right away, the next ones every interval
+        let interval = Duration::from_millis(100);
+        let canary = run_canary(upstreams, interval);
+        assert!(tokio::time::timeout(interval * 2 + interval / 2, canary)
+            .await
+            .is_err());
+
+        let rendered = handle.render();
+        assert!(
+            rendered
+                .lines()
+                .any(|line| line == r#"canary_requests_total{upstream="l2",outcome="success"} 3"#),
+            "{rendered}"
+        );
+        assert!(
+            rendered.lines().any(
+                |line| line == r#"canary_requests_total{upstream="builder",outcome="error"} 3"#
+            ),
+            "{rendered}"
+        );
+        assert!(rendered.contains(r#"canary_duration_seconds_count{upstream="l2"} 3"#));
+        assert!(!rendered.contains(r#"canary_duration_seconds_count{upstream="builder"}"#));
+
+        l2.stop()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use metrics::Gauge;
+use rand::Rng;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Thresholds controlling when a [CircuitBreaker] opens and probes again.
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures after which the circuit opens
+    pub failure_threshold: u32,
+    /// Time the circuit stays open before a probe request is let through
+    pub cooldown: Duration,
+    /// Upper bound of a random delay added to the cooldown, so the instances whose circuits
+    /// opened together do not all probe a recovering upstream at once. Zero disables it
+    pub probe_jitter: Duration,
+}
+
+impl CircuitBreakerConfig {
+    /// Returns the time the circuit stays open, the cooldown and up to `probe_jitter` more.
+    pub fn probe_delay(&self, rng: &mut impl Rng) -> Duration {
+        self.cooldown + rng.gen_range(Duration::ZERO..=self.probe_jitter)
+    }
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(5),
+            probe_jitter: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow to the upstream
+    Closed,
+    /// A single probe request is in flight to decide whether to close the circuit, another one
+    /// is let through if it has not completed after the probe delay
+    HalfOpen,
+    /// Requests to the upstream are skipped until the cooldown elapses
+    Open,
+}
+
+impl CircuitState {
+    /// Value reported by the circuit state gauge
+    fn as_gauge(self) -> f64 {
+        match self {
+            CircuitState::Closed => 0.0,
+            CircuitState::HalfOpen => 1.0,
+            CircuitState::Open => 2.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+    /// Time after `opened_at` the probe request is let through, and the probe's lease
+    probe_delay: Duration,
+    /// When the probe in flight was let through
+    probe_sent_at: Instant,
+}
+
+/// Tracks consecutive failures of an upstream and stops sending it requests while it is
+/// unhealthy.
+///
+/// The circuit opens after `failure_threshold` consecutive failures. Once `cooldown`, and the
+/// probe jitter drawn when it opened, have elapsed a single probe request is allowed through:
+/// its success closes the circuit, its failure opens it for another cooldown. A probe that
+/// never completes, e.g. because it was cancelled, is replaced by another one after the same
+/// delay so the circuit does not stay half-open.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+    /// Reports the current state, see [CircuitState::as_gauge]
+    gauge: Option<Gauge>,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        name: impl Into<String>,
+        config: CircuitBreakerConfig,
+        gauge: Option<Gauge>,
+    ) -> Self {
+        if let Some(gauge) = &gauge {
+            gauge.set(CircuitState::Closed.as_gauge());
+        }
+        Self {
+            name: name.into(),
+            config,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+                probe_delay: config.cooldown,
+                probe_sent_at: Instant::now(),
+            }),
+            gauge,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Returns whether a request may be sent to the upstream.
+    ///
+    /// The caller must report the outcome of an allowed request with [Self::record_success] or
+    /// [Self::record_failure].
+    pub fn allow(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => {
+                if inner.probe_sent_at.elapsed() < inner.probe_delay {
+                    return false;
+                }
+                debug!(
+                    target: "proxy::circuit_breaker",
+                    message = "probe did not complete, sending another one",
+                    upstream = %self.name,
+                );
+                inner.probe_sent_at = Instant::now();
+                true
+            }
+            CircuitState::Open => {
+                if inner.opened_at.elapsed() < inner.probe_delay {
+                    return false;
+                }
+                inner.probe_sent_at = Instant::now();
+                self.transition(&mut inner, CircuitState::HalfOpen);
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if inner.state != CircuitState::Closed {
+            self.transition(&mut inner, CircuitState::Closed);
+        }
+    }
+
+    /// Gives back a request allowed by [Self::allow] that was not sent, so the next request
+    /// probes the upstream when it was the probe.
+    pub fn release(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == CircuitState::HalfOpen {
+            // Still past the probe delay, the next allowed request is the probe
+            inner.state = CircuitState::Open;
+            if let Some(gauge) = &self.gauge {
+                gauge.set(CircuitState::Open.as_gauge());
+            }
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        let should_open = match inner.state {
+            CircuitState::Closed => inner.consecutive_failures >= self.config.failure_threshold,
+            CircuitState::HalfOpen => true,
+            // A request allowed before the circuit opened failed late
+            CircuitState::Open => false,
+        };
+        if should_open {
+            inner.opened_at = Instant::now();
+            inner.probe_delay = self.config.probe_delay(&mut rand::thread_rng());
+            self.transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, state: CircuitState) {
+        let from = inner.state;
+        inner.state = state;
+        if let Some(gauge) = &self.gauge {
+            gauge.set(state.as_gauge());
+        }
+
+        match state {
+            CircuitState::Open => warn!(
+                target: "proxy::circuit_breaker",
+                message = "circuit opened, skipping requests to upstream",
+                upstream = %self.name,
+                ?from,
+                failures = inner.consecutive_failures,
+                cooldown = ?inner.probe_delay,
+            ),
+            _ => info!(
+                target: "proxy::circuit_breaker",
+                message = "circuit state changed",
+                upstream = %self.name,
+                ?from,
+                to = ?state,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(failure_threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "builder",
+            CircuitBreakerConfig {
+                failure_threshold,
+                cooldown,
+                probe_jitter: Duration::ZERO,
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn test_probe_delay_jitter() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let config = CircuitBreakerConfig {
+            failure_threshold: 1,
+            cooldown: Duration::from_secs(5),
+            probe_jitter: Duration::from_secs(2),
+        };
+        let delays: Vec<Duration> = (0..100).map(|_| config.probe_delay(&mut rng)).collect();
+        assert!(delays
+            .iter()
+            .all(|delay| (Duration::from_secs(5)..=Duration::from_secs(7)).contains(delay)));
+        // The probes are spread over the window rather than all sent after the cooldown
+        assert!(delays.iter().any(|delay| *delay < Duration::from_secs(6)));
+        assert!(delays.iter().any(|delay| *delay > Duration::from_secs(6)));
+
+        // Without jitter, the probe is sent right after the cooldown
+        let config = CircuitBreakerConfig {
+            probe_jitter: Duration::ZERO,
+            ..config
+        };
+        assert_eq!(config.probe_delay(&mut rng), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = breaker(3, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let breaker = breaker(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_probe_after_cooldown() {
+        let breaker = breaker(1, Duration::from_millis(20));
+
+        breaker.record_failure();
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // Only a single probe is in flight at a time
+        assert!(!breaker.allow());
+
+        // A failed probe opens the circuit for another cooldown
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_lost_probe_is_replaced() {
+        let breaker = breaker(1, Duration::from_millis(20));
+
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+
+        // The probe never reported its outcome, another one is let through after the delay
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(!breaker.allow());
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_released_probe() {
+        let breaker = breaker(1, Duration::from_millis(20));
+
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow());
+
+        // The probe was not sent, the next request probes the upstream instead
+        breaker.release();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        // Releasing a request of a closed circuit leaves it closed
+        breaker.record_success();
+        breaker.release();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
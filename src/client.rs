@@ -1,10 +1,13 @@
+use crate::auth::{AuthClientLayer, AuthClientService, SharedSecret};
 use clap::{arg, Parser};
 use http::Uri;
 use jsonrpsee::http_client::transport::HttpBackend;
 use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
 use paste::paste;
-use reth_rpc_layer::{AuthClientLayer, AuthClientService, JwtSecret};
+use reth_rpc_layer::JwtSecret;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
@@ -29,16 +32,21 @@ pub struct ExecutionClient {
     pub auth_client: Arc<HttpClient<AuthClientService<HttpBackend>>>,
     /// Uri of the RPC server for authenticated Engine API calls
     pub auth_rpc: Uri,
+    /// Secret the Engine API calls are signed with, replaced when it is rotated
+    pub auth_secret: SharedSecret,
+    /// Time allowed for a call to complete
+    pub timeout: Duration,
 }
 
 impl ExecutionClient {
     /// Initializes a new [ExecutionClient] with JWT auth for the Engine API and without auth for general execution layer APIs.
     pub fn new(
         auth_rpc: Uri,
-        auth_rpc_jwt_secret: JwtSecret,
+        auth_rpc_jwt_secret: impl Into<SharedSecret>,
         timeout: u64,
     ) -> Result<Self, ExecutionClientError> {
-        let auth_layer = AuthClientLayer::new(auth_rpc_jwt_secret);
+        let auth_secret = auth_rpc_jwt_secret.into();
+        let auth_layer = AuthClientLayer::new(auth_secret.clone());
         let auth_client = HttpClientBuilder::new()
             .set_http_middleware(tower::ServiceBuilder::new().layer(auth_layer))
             .request_timeout(Duration::from_millis(timeout))
@@ -47,6 +55,69 @@ impl ExecutionClient {
         Ok(Self {
             auth_client: Arc::new(auth_client),
             auth_rpc,
+            auth_secret,
+            timeout: Duration::from_millis(timeout),
+        })
+    }
+
+    /// Returns the uri and timeout of the client, leaving out its secret.
+    pub fn config(&self) -> UpstreamConfig {
+        UpstreamConfig::new(&self.auth_rpc, self.timeout)
+    }
+}
+
+/// An upstream as reported by `admin_rollupBoostConfig`, without its JWT secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamConfig {
+    /// Uri of the upstream without the user info and query, which may hold credentials
+    pub url: String,
+    pub timeout_ms: u64,
+}
+
+impl UpstreamConfig {
+    pub fn new(uri: &Uri, timeout: Duration) -> Self {
+        let mut url = String::new();
+        if let Some(scheme) = uri.scheme_str() {
+            url.push_str(scheme);
+            url.push_str("://");
+        }
+        if let Some(authority) = uri.authority() {
+            let authority = authority.as_str();
+            url.push_str(
+                authority
+                    .rsplit_once('@')
+                    .map_or(authority, |(_, host)| host),
+            );
+        }
+        url.push_str(uri.path());
+        Self {
+            url,
+            timeout_ms: timeout.as_millis() as u64,
+        }
+    }
+}
+
+/// An additional builder given on the command line as `<URL>,<JWT_PATH>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuilderEndpoint {
+    pub url: Uri,
+    /// Path to the JWT secret used to authenticate with the builder
+    pub jwt_path: PathBuf,
+}
+
+impl FromStr for BuilderEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (url, jwt_path) = s
+            .split_once(',')
+            .ok_or_else(|| format!("expected <URL>,<JWT_PATH>, got {s}"))?;
+        Ok(Self {
+            url: url
+                .parse()
+                .map_err(|e| format!("invalid builder url {url}: {e}"))?,
+            jwt_path: PathBuf::from(jwt_path),
         })
     }
 }
@@ -63,7 +134,12 @@ macro_rules! define_rpc_args {
                     pub [<$prefix _url>]: Uri,
 
                     /// Hex encoded JWT secret to use for the authenticated engine-API RPC server.
-                    #[arg(long, env, value_name = "HEX")]
+                    #[arg(
+                        long,
+                        env,
+                        value_name = "HEX",
+                        conflicts_with = stringify!([<$prefix _jwt_path>])
+                    )]
                     pub [<$prefix _jwt_token>]: Option<JwtSecret>,
 
                     /// Path to a JWT secret to use for the authenticated engine-API RPC server.
@@ -0,0 +1,192 @@
+use flate2::Compression;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    #[error("unsupported content encoding {0}")]
+    Unsupported(String),
+    #[error("decoded body exceeds {0} bytes")]
+    TooLarge(u32),
+    #[error("invalid compressed body: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A content coding of an HTTP body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    /// zlib wrapped deflate, as `deflate` is defined by HTTP
+    Deflate,
+    Brotli,
+}
+
+impl FromStr for Encoding {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "deflate" => Ok(Encoding::Deflate),
+            "br" => Ok(Encoding::Brotli),
+            other => Err(DecodeError::Unsupported(other.to_string())),
+        }
+    }
+}
+
+impl Encoding {
+    /// Token of the coding in `Content-Encoding` and `Accept-Encoding` headers
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// Picks the coding of a response from the request's `Accept-Encoding` header: the supported
+/// coding with the highest weight, gzip among equally weighted ones.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or_default().trim();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(1.0, |quality| quality.trim().parse().unwrap_or(0.0));
+        if quality <= 0.0 {
+            continue;
+        }
+
+        let encoding = if coding == "*" {
+            Encoding::Gzip
+        } else if let Ok(encoding) = coding.parse() {
+            encoding
+        } else {
+            continue;
+        };
+        let better = match best {
+            None => true,
+            Some((current, current_quality)) => {
+                quality > current_quality
+                    || (quality == current_quality
+                        && encoding == Encoding::Gzip
+                        && current != Encoding::Gzip)
+            }
+        };
+        if better {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Compresses a body, favouring speed over ratio since responses are on the critical path.
+pub fn encode(encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], Compression::fast());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = flate2::write::ZlibEncoder::new(vec![], Compression::fast());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut encoded = vec![];
+            {
+                let mut writer = brotli::CompressorWriter::new(&mut encoded, 4096, 4, 22);
+                writer.write_all(body)?;
+            }
+            Ok(encoded)
+        }
+    }
+}
+
+/// Decodes a body compressed with the codings of a `Content-Encoding` header, applied in the
+/// listed order. Fails once the decoded body grows past `max_size` so a small compressed body
+/// cannot expand without bound.
+pub fn decode(
+    content_encoding: &str,
+    body: Vec<u8>,
+    max_size: u32,
+) -> Result<Vec<u8>, DecodeError> {
+    let encodings = content_encoding
+        .split(',')
+        .filter(|encoding| !encoding.trim().eq_ignore_ascii_case("identity"))
+        .map(Encoding::from_str)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    encodings
+        .into_iter()
+        .rev()
+        .try_fold(body, |body, encoding| {
+            let reader: Box<dyn Read + '_> = match encoding {
+                Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(body.as_slice())),
+                Encoding::Deflate => Box::new(flate2::read::ZlibDecoder::new(body.as_slice())),
+                Encoding::Brotli => Box::new(brotli::Decompressor::new(body.as_slice(), 4096)),
+            };
+
+            let mut decoded = vec![];
+            reader
+                .take(u64::from(max_size) + 1)
+                .read_to_end(&mut decoded)?;
+            if decoded.len() > max_size as usize {
+                return Err(DecodeError::TooLarge(max_size));
+            }
+            Ok(decoded)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = br#"{"jsonrpc":"2.0","method":"eth_chainId","id":1}"#;
+
+    #[test]
+    fn test_decode() {
+        for encoding in [Encoding::Gzip, Encoding::Deflate, Encoding::Brotli] {
+            let encoded = encode(encoding, BODY).unwrap();
+            assert_ne!(encoded, BODY);
+            assert_eq!(decode(encoding.as_str(), encoded, 1024).unwrap(), BODY);
+        }
+
+        // Codings are undone from the last applied
+        let encoded = encode(Encoding::Brotli, &encode(Encoding::Gzip, BODY).unwrap()).unwrap();
+        assert_eq!(decode("gzip, br", encoded, 1024).unwrap(), BODY);
+        assert_eq!(decode("identity", BODY.to_vec(), 1024).unwrap(), BODY);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(matches!(
+            decode("zstd", vec![], 1024),
+            Err(DecodeError::Unsupported(encoding)) if encoding == "zstd"
+        ));
+        assert!(matches!(
+            decode("gzip", b"not gzip".to_vec(), 1024),
+            Err(DecodeError::Io(_))
+        ));
+        assert!(matches!(
+            decode("gzip", encode(Encoding::Gzip, &[b' '; 2048]).unwrap(), 1024),
+            Err(DecodeError::TooLarge(1024))
+        ));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=1.0, gzip;q=0.8"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("zstd, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("*"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+}
@@ -0,0 +1,53 @@
+use clap::Command;
+use eyre::{bail, WrapErr};
+use std::path::Path;
+
+/// Loads the values of a TOML config file into the environment variables of the matching
+/// arguments, so flags and variables that are already set keep precedence over the file.
+///
+/// Keys are the argument names as written in `Args`, e.g. `l2_url` or `rpc_port`. Must be
+/// called before any other thread is started, as it sets environment variables.
+pub fn load(command: &Command, path: &Path) -> eyre::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read config file {}", path.display()))?;
+    let table: toml::Table = contents
+        .parse()
+        .wrap_err_with(|| format!("failed to parse config file {}", path.display()))?;
+
+    for (key, value) in table {
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_id().as_str() == key)
+        else {
+            bail!("unknown key {key} in config file {}", path.display());
+        };
+        let Some(env) = arg.get_env() else {
+            bail!("{key} cannot be set from a config file");
+        };
+        if std::env::var_os(env).is_some() {
+            continue;
+        }
+
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Array(values) => {
+                let Some(delimiter) = arg.get_value_delimiter() else {
+                    bail!("{key} does not accept a list");
+                };
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        toml::Value::String(value) => value,
+                        value => value.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(&delimiter.to_string())
+            }
+            toml::Value::Table(_) => bail!("{key} does not accept a table"),
+            value => value.to_string(),
+        };
+        std::env::set_var(env, value);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use crate::metrics::{InflightGuard, ProxyMetrics};
+use http::Uri;
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use std::future::Future;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::Service;
+
+/// Wraps the connector of the upstream client to count the connections open to each upstream,
+/// idle or not, in the `upstream_connections` gauge. The pool of the client does not report them.
+#[derive(Debug, Clone)]
+pub struct CountingConnector<C> {
+    inner: C,
+    /// Scheme and authority of each upstream, with the name its connections are counted under
+    upstreams: Arc<[(Uri, &'static str)]>,
+    metrics: Option<Arc<ProxyMetrics>>,
+}
+
+impl<C> CountingConnector<C> {
+    /// Counts the connections opened by `inner` to `upstreams` when `metrics` are enabled.
+    /// Connections to other uris are not counted.
+    pub fn new(
+        inner: C,
+        upstreams: Vec<(Uri, &'static str)>,
+        metrics: Option<Arc<ProxyMetrics>>,
+    ) -> Self {
+        Self {
+            inner,
+            upstreams: upstreams.into(),
+            metrics,
+        }
+    }
+
+    /// Returns the name of the upstream at `uri`, the first one if several share its address.
+    fn upstream(&self, uri: &Uri) -> Option<&'static str> {
+        self.upstreams
+            .iter()
+            .find(|(upstream, _)| {
+                upstream.scheme() == uri.scheme() && upstream.authority() == uri.authority()
+            })
+            .map(|(_, name)| *name)
+    }
+}
+
+impl<C> Service<Uri> for CountingConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = CountedConnection<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let upstream = self.upstream(&uri);
+        let metrics = self.metrics.clone();
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let inner = connecting.await?;
+            // Counted once open, until the pool drops it
+            let _guard = metrics
+                .zip(upstream)
+                .map(|(metrics, upstream)| metrics.track_upstream_connection(upstream));
+            Ok(CountedConnection { inner, _guard })
+        })
+    }
+}
+
+/// A connection counted by a [CountingConnector] until it is dropped.
+pub struct CountedConnection<T> {
+    inner: T,
+    _guard: Option<InflightGuard>,
+}
+
+impl<T: Connection> Connection for CountedConnection<T> {
+    fn connected(&self) -> Connected {
+        self.inner.connected()
+    }
+}
+
+impl<T: Read + Unpin> Read for CountedConnection<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: Write + Unpin> Write for CountedConnection<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_of_uri() {
+        let connector = CountingConnector::new(
+            (),
+            vec![
+                ("http://127.0.0.1:8551".parse().unwrap(), "l2"),
+                ("https://builder.example.com".parse().unwrap(), "builder"),
+                ("http://127.0.0.1:8551".parse().unwrap(), "mirror"),
+            ],
+            None,
+        );
+        let upstream = |uri: &str| connector.upstream(&uri.parse().unwrap());
+        assert_eq!(upstream("http://127.0.0.1:8551/"), Some("l2"));
+        assert_eq!(upstream("https://BUILDER.example.com"), Some("builder"));
+        assert_eq!(upstream("http://builder.example.com"), None);
+        assert_eq!(upstream("http://127.0.0.1:8552"), None);
+    }
+}
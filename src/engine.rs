@@ -0,0 +1,103 @@
+/// Prefix of the engine API methods
+pub const ENGINE_PREFIX: &str = "engine_";
+
+/// Prefixes of the engine API methods only the l2 answers, which are never sent to a builder
+/// whatever the routing of the other engine methods. Blobs are retrieved from the local node.
+pub const L2_ONLY_METHODS: [&str; 1] = ["engine_getBlobs"];
+
+/// Whether `method` is answered by the l2 only, see [L2_ONLY_METHODS].
+pub fn is_l2_only(method: &str) -> bool {
+    L2_ONLY_METHODS
+        .iter()
+        .any(|prefix| method.starts_with(prefix))
+}
+
+/// The engine API methods rollup-boost selects payloads for. Other `engine_` methods, such as
+/// `engine_exchangeCapabilities` or the extensions some deployments add, are left to the l2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineMethod {
+    ForkchoiceUpdated,
+    GetPayload,
+    NewPayload,
+}
+
+impl EngineMethod {
+    /// Parses the name of a call into the method and its version, from `V1` to `V4`.
+    ///
+    /// Returns `None` for any other method, including versions with a suffix such as
+    /// `engine_newPayloadV3OP`.
+    pub fn parse(method: &str) -> Option<(Self, u8)> {
+        let name = method.strip_prefix(ENGINE_PREFIX)?;
+        let (name, version) = name.rsplit_once('V')?;
+        let method = match name {
+            "forkchoiceUpdated" => Self::ForkchoiceUpdated,
+            "getPayload" => Self::GetPayload,
+            "newPayload" => Self::NewPayload,
+            _ => return None,
+        };
+        let version = match version {
+            "1" => 1,
+            "2" => 2,
+            "3" => 3,
+            "4" => 4,
+            _ => return None,
+        };
+        Some((method, version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_engine_methods() {
+        for version in 1..=4 {
+            assert_eq!(
+                EngineMethod::parse(&format!("engine_forkchoiceUpdatedV{version}")),
+                Some((EngineMethod::ForkchoiceUpdated, version))
+            );
+            assert_eq!(
+                EngineMethod::parse(&format!("engine_getPayloadV{version}")),
+                Some((EngineMethod::GetPayload, version))
+            );
+            assert_eq!(
+                EngineMethod::parse(&format!("engine_newPayloadV{version}")),
+                Some((EngineMethod::NewPayload, version))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_other_methods() {
+        for method in [
+            "engine_getPayloadV0",
+            "engine_getPayloadV5",
+            "engine_getPayloadV10",
+            "engine_getPayload",
+            "engine_newPayloadV3OP",
+            "engine_newPayloadV",
+            "engine_getPayloadBodiesByHashV1",
+            "engine_exchangeCapabilities",
+            "engine_getClientVersionV1",
+            "eth_getPayloadV3",
+            "getPayloadV3",
+        ] {
+            assert_eq!(EngineMethod::parse(method), None, "{method}");
+        }
+    }
+
+    #[test]
+    fn test_l2_only_methods() {
+        assert!(is_l2_only("engine_getBlobsV1"));
+        assert!(is_l2_only("engine_getBlobsV2"));
+        for method in [
+            "engine_getPayloadV3",
+            "engine_getPayloadBodiesByHashV1",
+            "engine_exchangeCapabilities",
+            "eth_getBlobsV1",
+        ] {
+            assert!(!is_l2_only(method), "{method}");
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::compression::DecodeError;
+use crate::proxy::{InvalidUpstreamUri, SERVER_ERROR_CODE, UNAVAILABLE_CODE};
+use jsonrpsee::core::BoxError;
+use jsonrpsee::types::ErrorCode;
+use std::time::Duration;
+
+/// JSON-RPC code of the requests rejected for their authentication
+pub(crate) const UNAUTHORIZED_CODE: i32 = -32001;
+
+/// Why rollup-boost failed to answer a request, returned by the proxy and its forwards so the
+/// causes can be told apart without matching on messages.
+#[derive(Debug, thiserror::Error)]
+pub enum RollupBoostError {
+    /// The upstream did not respond in time
+    #[error("{upstream} did not respond within {timeout:?}")]
+    UpstreamTimeout {
+        upstream: &'static str,
+        timeout: Duration,
+    },
+    /// The upstream could not be reached, or closed the connection before responding
+    #[error("error forwarding request to {upstream}: {source}")]
+    UpstreamConnect {
+        upstream: &'static str,
+        #[source]
+        source: BoxError,
+    },
+    /// The request could not be decoded or parsed
+    #[error("invalid request: {0}")]
+    Parse(#[source] BoxError),
+    /// The request or the upstream credentials failed authentication
+    #[error("authentication failed: {0}")]
+    Auth(#[source] BoxError),
+    /// The configuration does not allow the request to be handled
+    #[error("invalid configuration: {0}")]
+    Config(#[source] BoxError),
+    /// Any other failure, such as one of the rpc server behind the proxy
+    #[error(transparent)]
+    Internal(BoxError),
+}
+
+impl RollupBoostError {
+    /// Returns the JSON-RPC code the error is answered with.
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::UpstreamTimeout { .. } => SERVER_ERROR_CODE,
+            Self::UpstreamConnect { .. } => UNAVAILABLE_CODE,
+            Self::Parse(_) => ErrorCode::ParseError.code(),
+            Self::Auth(_) => UNAUTHORIZED_CODE,
+            Self::Config(_) | Self::Internal(_) => ErrorCode::InternalError.code(),
+        }
+    }
+}
+
+/// Recovers the error a [RollupBoostError] was boxed from, any other error being internal.
+impl From<BoxError> for RollupBoostError {
+    fn from(error: BoxError) -> Self {
+        match error.downcast::<Self>() {
+            Ok(error) => *error,
+            Err(error) => match error.downcast::<DecodeError>() {
+                Ok(error) => Self::Parse(error),
+                Err(error) => Self::Internal(error),
+            },
+        }
+    }
+}
+
+impl From<DecodeError> for RollupBoostError {
+    fn from(error: DecodeError) -> Self {
+        Self::Parse(error.into())
+    }
+}
+
+impl From<serde_json::Error> for RollupBoostError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Parse(error.into())
+    }
+}
+
+impl From<reth_rpc_layer::JwtError> for RollupBoostError {
+    fn from(error: reth_rpc_layer::JwtError) -> Self {
+        Self::Auth(error.into())
+    }
+}
+
+impl From<InvalidUpstreamUri> for RollupBoostError {
+    fn from(error: InvalidUpstreamUri) -> Self {
+        Self::Config(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_rpc_layer::JwtSecret;
+
+    #[test]
+    fn test_error_codes() {
+        let timeout = RollupBoostError::UpstreamTimeout {
+            upstream: "l2",
+            timeout: Duration::from_millis(100),
+        };
+        assert_eq!(timeout.code(), -32000);
+        assert_eq!(timeout.to_string(), "l2 did not respond within 100ms");
+
+        let connect = RollupBoostError::UpstreamConnect {
+            upstream: "builder",
+            source: "connection refused".into(),
+        };
+        assert_eq!(connect.code(), -32002);
+        assert_eq!(
+            connect.to_string(),
+            "error forwarding request to builder: connection refused"
+        );
+
+        assert_eq!(RollupBoostError::Parse("eof".into()).code(), -32700);
+        assert_eq!(RollupBoostError::Auth("expired".into()).code(), -32001);
+        assert_eq!(RollupBoostError::Config("no builder".into()).code(), -32603);
+        assert_eq!(RollupBoostError::Internal("panic".into()).code(), -32603);
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        let error = RollupBoostError::from(serde_json::from_str::<u64>("not json").unwrap_err());
+        assert!(matches!(error, RollupBoostError::Parse(_)), "{error:?}");
+
+        let error = RollupBoostError::from(DecodeError::TooLarge(10));
+        assert!(matches!(error, RollupBoostError::Parse(_)), "{error:?}");
+
+        let error = RollupBoostError::from(JwtSecret::from_hex("not hex").unwrap_err());
+        assert!(matches!(error, RollupBoostError::Auth(_)), "{error:?}");
+
+        let error = RollupBoostError::from(
+            crate::proxy::validate_upstream_uri("l2", &"ftp://l2".parse().unwrap()).unwrap_err(),
+        );
+        assert!(matches!(error, RollupBoostError::Config(_)), "{error:?}");
+        assert!(
+            error
+                .to_string()
+                .starts_with("invalid configuration: invalid l2 uri"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_boxed_error_conversions() {
+        // A boxed error keeps its cause when it is converted back
+        let boxed: BoxError = RollupBoostError::UpstreamTimeout {
+            upstream: "l2",
+            timeout: Duration::from_secs(1),
+        }
+        .into();
+        let error = RollupBoostError::from(boxed);
+        assert!(
+            matches!(
+                error,
+                RollupBoostError::UpstreamTimeout { upstream: "l2", .. }
+            ),
+            "{error:?}"
+        );
+
+        let boxed: BoxError = DecodeError::Unsupported("zstd".to_string()).into();
+        assert!(matches!(
+            RollupBoostError::from(boxed),
+            RollupBoostError::Parse(_)
+        ));
+
+        let boxed: BoxError = std::io::Error::other("broken pipe").into();
+        let error = RollupBoostError::from(boxed);
+        assert!(matches!(error, RollupBoostError::Internal(_)), "{error:?}");
+        assert_eq!(error.to_string(), "broken pipe");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::client::ExecutionClient;
+use jsonrpsee::core::client::{ClientT, Error as ClientError};
+use jsonrpsee::rpc_params;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// How often the builder is checked by [monitor_builder], and the checks it takes to toggle it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheckConfig {
+    /// Time between two checks
+    pub interval: Duration,
+    /// Consecutive failed checks after which the builder is disabled
+    pub unhealthy_threshold: u32,
+    /// Consecutive successful checks after which a builder disabled by the monitor is enabled
+    /// again
+    pub healthy_threshold: u32,
+}
+
+/// A change of the builder toggle made by a [HealthTracker].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transition {
+    Disabled,
+    Enabled,
+}
+
+/// Counts the consecutive outcomes of the health checks and toggles the builder once a
+/// threshold is reached.
+///
+/// Only a builder disabled by the tracker is enabled again: one disabled through the admin API
+/// stays disabled, and enabling it through the admin API hands it back to the tracker.
+#[derive(Debug)]
+struct HealthTracker {
+    config: HealthCheckConfig,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    auto_disabled: bool,
+}
+
+impl HealthTracker {
+    fn new(config: HealthCheckConfig) -> Self {
+        Self {
+            config,
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            auto_disabled: false,
+        }
+    }
+
+    /// Records the outcome of a check, returning the change made to `builder_enabled` if any.
+    fn record(&mut self, healthy: bool, builder_enabled: &AtomicBool) -> Option<Transition> {
+        if healthy {
+            self.consecutive_failures = 0;
+            self.consecutive_successes = self.consecutive_successes.saturating_add(1);
+        } else {
+            self.consecutive_successes = 0;
+            self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        }
+
+        let enabled = builder_enabled.load(Ordering::Relaxed);
+        if self.auto_disabled && enabled {
+            // Enabled again through the admin API
+            self.auto_disabled = false;
+        }
+
+        if self.auto_disabled {
+            if self.consecutive_successes >= self.config.healthy_threshold {
+                builder_enabled.store(true, Ordering::Relaxed);
+                self.auto_disabled = false;
+                return Some(Transition::Enabled);
+            }
+        } else if enabled
+            && self.consecutive_failures >= self.config.unhealthy_threshold
+            // Left alone if it was disabled through the admin API in the meantime
+            && builder_enabled
+                .compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.auto_disabled = true;
+            return Some(Transition::Disabled);
+        }
+        None
+    }
+}
+
+/// Checks the builder every interval, disabling the engine calls and forwarded requests sent
+/// to it after consecutive failed checks and enabling them again once it passes enough
+/// consecutive checks.
+///
+/// A check is an authenticated `eth_chainId` call, which passes when the builder answers it,
+/// even with an error.
+pub async fn monitor_builder(
+    client: ExecutionClient,
+    builder_enabled: Arc<AtomicBool>,
+    config: HealthCheckConfig,
+) {
+    let mut tracker = HealthTracker::new(config);
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let healthy = check_builder(&client).await;
+        match tracker.record(healthy, &builder_enabled) {
+            Some(Transition::Disabled) => {
+                warn!(message = "builder failed its health checks, disabling it", url = %client.auth_rpc, checks = config.unhealthy_threshold)
+            }
+            Some(Transition::Enabled) => {
+                info!(message = "builder passed its health checks, enabling it again", url = %client.auth_rpc, checks = config.healthy_threshold)
+            }
+            None => {}
+        }
+    }
+}
+
+async fn check_builder(client: &ExecutionClient) -> bool {
+    let result = client
+        .auth_client
+        .request::<serde_json::Value, _>("eth_chainId", rpc_params![])
+        .await;
+    match result {
+        Ok(_) | Err(ClientError::Call(_)) => true,
+        Err(e) => {
+            debug!(message = "builder health check failed", url = %client.auth_rpc, error = %e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::server::Server;
+    use jsonrpsee::RpcModule;
+    use reth_rpc_layer::JwtSecret;
+    use tokio::net::TcpListener;
+
+    const CONFIG: HealthCheckConfig = HealthCheckConfig {
+        interval: Duration::from_millis(20),
+        unhealthy_threshold: 2,
+        healthy_threshold: 3,
+    };
+
+    #[test]
+    fn test_health_tracker() {
+        let builder_enabled = AtomicBool::new(true);
+        let mut tracker = HealthTracker::new(CONFIG);
+        let mut record = |healthy| tracker.record(healthy, &builder_enabled);
+
+        // A single failure is not enough to disable the builder
+        assert_eq!(record(false), None);
+        assert_eq!(record(true), None);
+        assert_eq!(record(false), None);
+        assert_eq!(record(false), Some(Transition::Disabled));
+        assert_eq!(record(false), None);
+
+        // It takes consecutive successes to enable it again
+        assert_eq!(record(true), None);
+        assert_eq!(record(true), None);
+        assert_eq!(record(false), None);
+        assert_eq!(record(true), None);
+        assert_eq!(record(true), None);
+        assert_eq!(record(true), Some(Transition::Enabled));
+        assert!(builder_enabled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_health_tracker_leaves_admin_toggle() {
+        // A builder disabled through the admin API is not enabled by the checks
+        let builder_enabled = AtomicBool::new(false);
+        let mut tracker = HealthTracker::new(CONFIG);
+        for _ in 0..5 {
+            assert_eq!(tracker.record(true, &builder_enabled), None);
+        }
+        for _ in 0..5 {
+            assert_eq!(tracker.record(false, &builder_enabled), None);
+        }
+        for _ in 0..5 {
+            assert_eq!(tracker.record(true, &builder_enabled), None);
+        }
+        assert!(!builder_enabled.load(Ordering::Relaxed));
+
+        // Once enabled through the admin API, it is disabled by the checks again
+        builder_enabled.store(true, Ordering::Relaxed);
+        assert_eq!(tracker.record(false, &builder_enabled), None);
+        assert_eq!(
+            tracker.record(false, &builder_enabled),
+            Some(Transition::Disabled)
+        );
+        // and enabled through the admin API before the checks pass, it is monitored as usual
+        builder_enabled.store(true, Ordering::Relaxed);
+        for _ in 0..5 {
+            assert_eq!(tracker.record(true, &builder_enabled), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_recovery() -> eyre::Result<()> {
+        // Reserve a port for the builder, which is down at first
+        let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let client =
+            ExecutionClient::new(format!("http://{addr}").parse()?, JwtSecret::random(), 100)?;
+        let builder_enabled = Arc::new(AtomicBool::new(true));
+        let monitor = tokio::spawn(monitor_builder(client, builder_enabled.clone(), CONFIG));
+
+        let wait_for = |enabled: bool| {
+            let builder_enabled = builder_enabled.clone();
+            tokio::time::timeout(Duration::from_secs(5), async move {
+                while builder_enabled.load(Ordering::Relaxed) != enabled {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+        };
+        wait_for(false).await?;
+
+        // The builder comes back up
+        let mut module = RpcModule::new(());
+        module.register_method("eth_chainId", |_, _, _| "0x1")?;
+        let server = Server::builder().build(addr).await?.start(module);
+        let recovered_at = tokio::time::Instant::now();
+        wait_for(true).await?;
+        // after the healthy checks it takes
+        assert!(recovered_at.elapsed() >= CONFIG.interval * (CONFIG.healthy_threshold - 1));
+
+        monitor.abort();
+        server.stop()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use jsonrpsee::core::BoxError;
+use jsonrpsee::http_client::{HttpBody, HttpRequest, HttpResponse};
+use jsonrpsee::server::StopHandle;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use tokio::net::UnixListener;
+use tower::Service;
+use tracing::{debug, error, info};
+
+/// Binds a Unix socket at `path`, removing a socket left behind by a previous run.
+///
+/// Fails if `path` is a socket another process still accepts connections on, or a file that is
+/// not a socket.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use", path.display()),
+                ));
+            }
+            info!(message = "removing stale rpc socket", path = %path.display());
+            std::fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    UnixListener::bind(path)
+}
+
+/// Serves HTTP requests accepted on `listener` with `service`, the rpc server with its
+/// middleware, until `stop_handle` is stopped. The socket file is removed once the listener
+/// closes.
+pub async fn serve<S>(listener: UnixListener, service: S, stop_handle: StopHandle)
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    loop {
+        let stream = tokio::select! {
+            _ = stop_handle.clone().shutdown() => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    error!(message = "Error accepting ipc connection", error = %e);
+                    continue;
+                }
+            },
+        };
+
+        let service = service.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<Incoming>| {
+                let mut service = service.clone();
+                service.call(req.map(HttpBody::new))
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(message = "Error serving ipc connection", error = %err);
+            }
+        });
+    }
+
+    if let Some(path) = listener
+        .local_addr()
+        .ok()
+        .and_then(|addr| addr.as_pathname().map(Path::to_path_buf))
+    {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::CONTENT_TYPE;
+    use http_body_util::BodyExt;
+    use jsonrpsee::server::{stop_channel, Server};
+    use jsonrpsee::RpcModule;
+    use std::path::PathBuf;
+    use tokio::net::UnixStream;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rollup-boost-{name}-{}.sock", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_bind_removes_stale_socket() -> eyre::Result<()> {
+        let path = socket_path("stale");
+        let _ = std::fs::remove_file(&path);
+
+        // The socket file outlives the listener, like after a crash
+        drop(bind(&path)?);
+        assert!(path.exists());
+        let listener = bind(&path)?;
+
+        // but a socket in use is left alone
+        let error = bind(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+        std::fs::remove_file(&path)?;
+
+        std::fs::write(&path, "not a socket")?;
+        let error = bind(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_over_unix_socket() -> eyre::Result<()> {
+        let path = socket_path("rpc");
+        let _ = std::fs::remove_file(&path);
+
+        let mut module = RpcModule::new(());
+        module.register_method("greet_melkor", |_, _, _| "You are the dark lord")?;
+        let (stop_handle, server_handle) = stop_channel();
+        let service = Server::builder()
+            .to_service_builder()
+            .build(module, stop_handle.clone());
+        let server = tokio::spawn(serve(bind(&path)?, service, stop_handle));
+
+        let stream = UnixStream::connect(&path).await?;
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        tokio::spawn(connection);
+        let response = sender
+            .send_request(
+                http::Request::post("/")
+                    .header(http::header::HOST, "localhost")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(HttpBody::from(
+                        r#"{"jsonrpc":"2.0","id":1,"method":"greet_melkor","params":[]}"#,
+                    ))?,
+            )
+            .await?;
+        let body = response.into_body().collect().await?.to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["result"], "You are the dark lord");
+
+        // Stopping the server closes the listener and removes the socket
+        server_handle.stop()?;
+        server.await?;
+        assert!(!path.exists());
+
+        Ok(())
+    }
+}
@@ -1,293 +1,2694 @@
-use clap::{arg, Parser};
-use client::{BuilderArgs, ExecutionClient, L2ClientArgs};
-use std::{net::SocketAddr, sync::Arc};
+use clap::{arg, CommandFactory, Parser, ValueEnum};
+use client::{BuilderArgs, BuilderEndpoint, ExecutionClient, L2ClientArgs};
+use std::{
+    fs::OpenOptions,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use alloy_primitives::U256;
+use auth::{SharedJwtValidator, SharedSecret, StaticTokenLayer};
+use circuit_breaker::CircuitBreakerConfig;
 use dotenv::dotenv;
-use eyre::bail;
-use http::StatusCode;
+use eyre::{bail, WrapErr};
+use health::HealthCheckConfig;
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{StatusCode, Uri};
 use hyper::service::service_fn;
 use hyper::{server::conn::http1, Request, Response};
 use hyper_util::rt::TokioIo;
 use jsonrpsee::http_client::HttpBody;
-use jsonrpsee::server::Server;
+use jsonrpsee::server::{stop_channel, Server, ServerHandle};
 use jsonrpsee::RpcModule;
-use metrics::ServerMetrics;
-use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use metrics::{
+    record_build_info, ProxyMetrics, ServerMetrics, BUILD_LATENCY_BUCKETS, FORWARD_DURATION_BUCKETS,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use metrics_util::layers::{PrefixLayer, Stack};
 use opentelemetry::global;
-use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::Config, Resource};
-use proxy::ProxyLayer;
-use reth_rpc_layer::JwtSecret;
-use server::RollupBoostServer;
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{Config, Sampler, TracerProvider},
+    Resource,
+};
+use proxy::{
+    ConnectConfig, ForwardHeader, MethodTimeout, PoolConfig, ProxyLayer, RetryConfig,
+    UpstreamErrorCode, UpstreamHttpVersion, DEFAULT_MAX_BUILDER_FORWARDS,
+    DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_MAX_REQUEST_SIZE, DEFAULT_STREAM_THRESHOLD,
+};
+use rate_limit::{RateLimitConfig, RateLimiter};
+use record::Recorder;
+use reth_rpc_layer::{AuthLayer, JwtSecret};
+use selection::SelectionStrategy;
+use server::{RollupBoostServer, SlotDeadline, DEFAULT_WIN_RATE_WINDOW};
+use socket2::{Domain, Protocol, Socket, Type};
+use subscription::SubscriptionProxy;
+use subtle::ConstantTimeEq;
+use tls::{https_connector, rpc_tls_acceptor, RpcTlsArgs, TlsArgs};
 
 use tokio::net::TcpListener;
 use tokio::signal::unix::{signal as unix_signal, SignalKind};
-use tracing::{error, info, Level};
-use tracing_subscriber::EnvFilter;
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info, warn, Level};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
+mod auth;
+mod canary;
+mod circuit_breaker;
 mod client;
+mod compression;
+mod config;
+mod connection;
+mod engine;
+mod error;
+mod health;
 #[cfg(all(feature = "integration", test))]
 mod integration;
+mod ipc;
 mod metrics;
 mod proxy;
+mod rate_limit;
+mod record;
+mod selection;
 mod server;
+mod subscription;
+mod tls;
+
+/// Default maximum number of connections open to the rpc server, as in jsonrpsee
+const DEFAULT_MAX_CONNECTIONS: u32 = 100;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct Args {
+    /// TOML file providing arguments by name, overridden by flags and environment variables
+    #[arg(long, env, value_name = "PATH")]
+    config: Option<PathBuf>,
+
     #[clap(flatten)]
     builder: BuilderArgs,
 
-    #[clap(flatten)]
-    l2_client: L2ClientArgs,
+    #[clap(flatten)]
+    l2_client: L2ClientArgs,
+
+    #[clap(flatten)]
+    tls: TlsArgs,
+
+    #[clap(flatten)]
+    rpc_tls: RpcTlsArgs,
+
+    /// Additional builder that forwarded requests are mirrored to, as `<URL>,<JWT_PATH>`.
+    /// Can be repeated
+    #[arg(
+        long = "builder",
+        env,
+        value_delimiter = ';',
+        value_name = "URL,JWT_PATH"
+    )]
+    builders: Vec<BuilderEndpoint>,
+
+    /// Second l2 that requests forwarded by the proxy, and the engine calls of the
+    /// rollup-boost server, are sent to while the l2 cannot be reached
+    #[arg(long, env, value_name = "URL")]
+    l2_failover_url: Option<Uri>,
+
+    /// Path to the JWT secret of the failover l2, the l2 secret if unset
+    #[arg(long, env, value_name = "PATH", requires = "l2_failover_url")]
+    l2_failover_jwt_path: Option<PathBuf>,
+
+    /// Time in milliseconds requests stay on the failover l2 before the l2 is probed again
+    #[arg(long, env, default_value_t = 5000)]
+    l2_failover_cooldown: u64,
+
+    /// Write a random JWT secret to `--l2-jwt-path` and `--builder-jwt-path` when they do not
+    /// exist, e.g. for local development
+    #[arg(long, env, default_value = "false")]
+    generate_jwt: bool,
+
+    /// Serve the `admin` namespace, e.g. `admin_setBuilderEnabled`, on the rpc server without
+    /// authentication
+    #[arg(long, env, default_value = "false")]
+    enable_admin_api: bool,
+
+    /// Use the proposer to sync the builder node
+    #[arg(long, env, default_value = "false")]
+    boost_sync: bool,
+
+    /// Times a forkchoice update is sent again to a builder answering `SYNCING`, with boost sync
+    #[arg(long, env, default_value_t = 3)]
+    builder_sync_retries: u32,
+
+    /// Time in milliseconds between two forkchoice updates sent to a syncing builder
+    #[arg(long, env, default_value_t = 500)]
+    builder_sync_retry_interval: u64,
+
+    /// Host to run the server on
+    #[arg(long, env, default_value = "0.0.0.0")]
+    rpc_host: String,
+
+    /// Port to run the server on
+    #[arg(long, env, default_value = "8081")]
+    rpc_port: u16,
+
+    /// Accept IPv4 connections on servers bound to an IPv6 address such as `::`, which otherwise
+    /// only accept IPv6
+    #[arg(long, env, default_value = "false")]
+    dual_stack: bool,
+
+    /// Unix socket the rpc server also listens on, with the same proxy in front of it. A socket
+    /// left behind by a previous run is replaced
+    #[arg(long, env, value_name = "PATH")]
+    rpc_ipc_path: Option<PathBuf>,
+
+    /// Token clients may send as an `Authorization: Bearer` header to call the methods outside
+    /// the engine and admin namespaces without a JWT. When set, other requests need a JWT
+    /// signed with the l2 secret
+    #[arg(long, env, value_name = "TOKEN")]
+    rpc_static_token: Option<String>,
+
+    /// Port of a second rpc server for public calls, serving the same methods except the engine
+    /// and admin ones. Disabled if unset
+    #[arg(long, env)]
+    public_rpc_port: Option<u16>,
+
+    /// Host the public rpc server listens on, `--rpc-host` if unset
+    #[arg(long, env)]
+    public_rpc_host: Option<String>,
+
+    /// Serve the rpc server without the proxy, so every request reaches the engine and admin
+    /// methods of rollup-boost directly and other methods are not forwarded. For debugging the
+    /// payload selection locally
+    #[arg(
+        long,
+        env,
+        default_value_t = false,
+        conflicts_with_all = [
+            "public_rpc_port",
+            "builder_only",
+            "rate_limit",
+            "engine_rate_limit",
+            "rpc_static_token",
+        ]
+    )]
+    no_proxy: bool,
+
+    /// Port of a WebSocket server serving `eth_subscribe` by subscribing to the l2, its clients
+    /// authenticated like those of the rpc server. Disabled if unset
+    #[arg(long, env)]
+    ws_port: Option<u16>,
+
+    /// Maximum size in bytes of a request body accepted by the proxy and the rpc server
+    #[arg(long, env, default_value_t = DEFAULT_MAX_REQUEST_SIZE)]
+    max_request_size: u32,
+
+    /// Size in bytes past which the body of a call forwarded to the l2 or a single builder is
+    /// streamed to it rather than buffered, when its method and id come before its params. Calls
+    /// to the l2 are not streamed with a failover l2
+    #[arg(long, env, default_value_t = DEFAULT_STREAM_THRESHOLD)]
+    stream_threshold: u32,
+
+    /// Maximum number of connections open to the rpc server, connections over the limit are
+    /// refused
+    #[arg(long, env, default_value_t = DEFAULT_MAX_CONNECTIONS)]
+    max_connections: u32,
+
+    /// Compress responses with gzip, deflate or brotli for clients sending `Accept-Encoding`
+    #[arg(long, env, default_value = "false")]
+    response_compression: bool,
+
+    /// Answer `/healthz` with the version and uptime as JSON instead of `OK`
+    #[arg(long, env, default_value = "false")]
+    healthz_verbose: bool,
+
+    /// Requests per second accepted by the proxy, engine API calls excluded. Unlimited if unset
+    #[arg(long, env, value_name = "RPS", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit: Option<u32>,
+
+    /// Requests accepted at once above the rate limit (default: the rate limit)
+    #[arg(long, env, requires = "rate_limit", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit_burst: Option<u32>,
+
+    /// Engine API calls per second accepted by the proxy. Unlimited if unset
+    #[arg(long, env, value_name = "RPS", value_parser = clap::value_parser!(u32).range(1..))]
+    engine_rate_limit: Option<u32>,
+
+    /// Engine API calls accepted at once above the engine rate limit (default: the engine rate
+    /// limit)
+    #[arg(long, env, requires = "engine_rate_limit", value_parser = clap::value_parser!(u32).range(1..))]
+    engine_rate_limit_burst: Option<u32>,
+
+    /// Append every request handled by the proxy and the response returned for it to this file,
+    /// one JSON object per line
+    #[arg(long, env, value_name = "PATH")]
+    record_file: Option<PathBuf>,
+
+    /// Comma-separated method prefixes intercepted by rollup-boost instead of forwarded to the l2
+    #[arg(long, env, value_delimiter = ',')]
+    multiplex_methods: Option<Vec<String>>,
+
+    /// Comma-separated multiplexed method prefixes mirrored to both the l2 and the builder
+    #[arg(long, env, value_delimiter = ',')]
+    forward_methods: Option<Vec<String>>,
+
+    /// Comma-separated method prefixes sent to the builder, the engine calls of rollup-boost
+    /// included. Other methods are answered by the l2 alone
+    #[arg(long, env, value_delimiter = ',')]
+    builder_methods: Option<Vec<String>>,
+
+    /// Mirror `eth_sendRawTransaction` to the builders, disable to send raw transactions to the
+    /// l2 only
+    #[arg(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    forward_raw_txs_to_builder: bool,
+
+    /// Timeout of the calls to methods starting with a prefix, used instead of the l2 and builder
+    /// timeouts, e.g. `engine_getPayload=2s`. The longest matching prefix applies
+    #[arg(
+        long = "method-timeout",
+        env = "METHOD_TIMEOUTS",
+        value_name = "PREFIX=DURATION",
+        value_delimiter = ','
+    )]
+    method_timeouts: Vec<MethodTimeout>,
+
+    /// JSON-RPC code answered when a forwarded call fails with `connect`, `timeout`, `tls` or
+    /// `5xx` instead of the default one, e.g. `connect=-32099`. The default codes are -32002,
+    /// and -32000 for timeouts
+    #[arg(
+        long = "upstream-error-code",
+        env = "UPSTREAM_ERROR_CODES",
+        value_name = "FAILURE=CODE",
+        value_delimiter = ','
+    )]
+    upstream_error_codes: Vec<UpstreamErrorCode>,
+
+    /// Header added to the requests forwarded to the builders, e.g. an API key or the origin of
+    /// the orderflow, as `NAME=VALUE`. Can be repeated
+    #[arg(
+        long = "forward-header",
+        env = "FORWARD_HEADERS",
+        value_name = "NAME=VALUE",
+        value_delimiter = ';'
+    )]
+    forward_headers: Vec<ForwardHeader>,
+
+    /// Header removed from the requests before they are handled. Hop-by-hop headers such as
+    /// `Connection` are never forwarded. Can be repeated
+    #[arg(
+        long = "strip-header",
+        env = "STRIP_HEADERS",
+        value_name = "NAME",
+        value_delimiter = ','
+    )]
+    strip_headers: Vec<http::HeaderName>,
+
+    /// Send `eth_sendBundle` to the l2 too, which then answers it, instead of to the builders
+    /// only
+    #[arg(long, env, default_value = "false")]
+    forward_bundles_to_l2: bool,
+
+    /// Maximum attempts when an upstream refuses the connection of a forwarded request
+    #[arg(long, env, default_value_t = 3)]
+    forward_max_attempts: u32,
+
+    /// Delay in milliseconds before retrying a forwarded request, doubled on every retry
+    #[arg(long, env, default_value_t = 10)]
+    forward_retry_base_delay: u64,
+
+    /// Maximum delay in milliseconds between two attempts of a forwarded request
+    #[arg(long, env, default_value_t = 500)]
+    forward_retry_max_delay: u64,
+
+    /// Wait a random delay between zero and the backoff before retrying a forwarded request
+    #[arg(long, env, default_value = "false")]
+    forward_retry_jitter: bool,
+
+    /// Time in milliseconds the builder has to return its payload, and the l2 to validate it,
+    /// before the local payload is returned instead
+    #[arg(long, env, default_value_t = 1000)]
+    builder_payload_timeout: u64,
+
+    /// Time in milliseconds between two blocks. When set, the builder has until a fraction of
+    /// the slot after the forkchoice update that started the job to return its payload, instead
+    /// of the builder payload timeout
+    #[arg(long, env, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    slot_time: Option<u64>,
+
+    /// Share of the slot, from the forkchoice update, the builder has to return its payload in,
+    /// with `--slot-time`
+    #[arg(long, env, default_value_t = 0.5)]
+    builder_deadline_fraction: f64,
+
+    /// Time in milliseconds a returned payload is served again for repeated getPayload calls
+    #[arg(long, env, default_value_t = 2000)]
+    payload_cache_ttl: u64,
+
+    /// How the payload returned by getPayload is picked when both the l2 and the builder return
+    /// a valid one
+    #[arg(long, env, value_enum, default_value_t = SelectionStrategy::HigherValue)]
+    selection_strategy: SelectionStrategy,
+
+    /// Value in wei the builder payload must exceed the local payload by to be proposed instead,
+    /// with the `higher-value` selection strategy
+    #[arg(long, env, default_value = "0", value_name = "WEI")]
+    min_builder_value_delta: U256,
+
+    /// Number of returned payloads the builder win rate metric is computed over
+    #[arg(long, env, default_value_t = DEFAULT_WIN_RATE_WINDOW, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    win_rate_window: usize,
+
+    /// Fetch and validate builder payloads but always return the local payload
+    #[arg(long, env, default_value_t = false)]
+    builder_shadow_mode: bool,
+
+    /// Send every engine call to the builder only, which answers it, to test a builder on its
+    /// own. The l2 gets no engine call and builder payloads are not validated: for diagnostics
+    /// only, never on a sequencer whose blocks matter
+    #[arg(
+        long,
+        env,
+        default_value_t = false,
+        conflicts_with = "builder_shadow_mode"
+    )]
+    builder_only: bool,
+
+    /// Reject the calls whose `jsonrpc` member is missing or not `2.0` with `-32600 Invalid
+    /// request`, instead of passing them on to the upstreams
+    #[arg(long, env, default_value_t = false)]
+    strict_jsonrpc: bool,
+
+    /// Idle connections kept open to each upstream for forwarded requests
+    #[arg(long, env, default_value_t = 32)]
+    pool_max_idle_per_host: usize,
+
+    /// Time in milliseconds after which an idle upstream connection is closed
+    #[arg(long, env, default_value_t = 90000)]
+    pool_idle_timeout: u64,
+
+    /// HTTP version requests are forwarded to the upstreams with. HTTP/2 multiplexes them over
+    /// a connection, spoken with prior knowledge over `http` and negotiated with ALPN over `https`
+    #[arg(long, env, value_enum, default_value_t = UpstreamHttpVersion::Http1)]
+    upstream_http_version: UpstreamHttpVersion,
+
+    /// Disable Nagle's algorithm on upstream connections, so small engine API requests are sent
+    /// right away
+    #[arg(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    tcp_nodelay: bool,
+
+    /// Interval in milliseconds of the TCP keep-alive probes on upstream connections, 0 to
+    /// disable them
+    #[arg(long, env, default_value_t = 60000)]
+    tcp_keepalive: u64,
+
+    /// Time in milliseconds allowed to connect to an upstream, 0 for no limit
+    #[arg(long, env, default_value_t = 1000)]
+    connect_timeout: u64,
+
+    /// Consecutive failed builder forwards after which forwarding to the builder is suspended
+    #[arg(long, env, default_value_t = 5)]
+    builder_circuit_failure_threshold: u32,
+
+    /// Time in milliseconds forwarding to the builder stays suspended before it is probed again
+    #[arg(long, env, default_value_t = 5000)]
+    builder_circuit_cooldown: u64,
+
+    /// Maximum random delay in milliseconds added to the cooldown before the builder is probed,
+    /// 0 to probe right after the cooldown
+    #[arg(long, env, default_value_t = 0)]
+    builder_circuit_probe_jitter: u64,
+
+    /// Time in milliseconds between two health checks of the builder. When set, the builder is
+    /// disabled after consecutive failed checks and enabled again once it is healthy
+    #[arg(long, env, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    builder_health_check_interval: Option<u64>,
+
+    /// Consecutive failed health checks after which the builder is disabled
+    #[arg(long, env, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    builder_unhealthy_threshold: u32,
+
+    /// Consecutive passed health checks after which a builder disabled by the health checks is
+    /// enabled again
+    #[arg(long, env, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    builder_healthy_threshold: u32,
+
+    /// Time in milliseconds between two canary `engine_exchangeCapabilities` calls to the l2 and
+    /// the builder, whose outcome and duration are recorded in the `canary_` metrics. Disabled if
+    /// unset
+    #[arg(long, env, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    canary_interval: Option<u64>,
+
+    /// Maximum number of requests mirrored to the builders at once, requests over the limit are
+    /// only sent to the l2. `--builder-max-concurrency` is an alias, not a separate limit
+    #[arg(long, env, alias = "builder-max-concurrency", default_value_t = DEFAULT_MAX_BUILDER_FORWARDS, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_builder_forwards: usize,
+
+    /// Maximum number of RPC requests handled at once, further requests wait for one to complete.
+    /// The health probes are not counted
+    #[arg(long, env, default_value_t = DEFAULT_MAX_CONCURRENT_REQUESTS, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrent_requests: usize,
+
+    /// Time in milliseconds to wait for in-flight requests to complete on shutdown
+    #[arg(long, env, default_value_t = 5000)]
+    shutdown_timeout: u64,
+
+    // Enable tracing
+    #[arg(long, env, default_value = "false")]
+    tracing: bool,
+
+    // Enable Prometheus metrics
+    #[arg(long, env, default_value = "false")]
+    metrics: bool,
+
+    /// Host to run the metrics server on
+    #[arg(long, env, default_value = "0.0.0.0")]
+    metrics_host: String,
+
+    /// Port to run the metrics server on
+    #[arg(long, env, default_value = "9090")]
+    metrics_port: u16,
+
+    /// Path the metrics are served on
+    #[arg(long, env, default_value = "/metrics")]
+    metrics_path: String,
+
+    /// Token the metrics server requires as an `Authorization: Bearer` header. Unauthenticated
+    /// if unset
+    #[arg(long, env, value_name = "TOKEN")]
+    metrics_auth_token: Option<String>,
+
+    /// OTLP endpoint, the traces URL when exporting over HTTP [default: http://localhost:4317
+    /// over gRPC, http://localhost:4318/v1/traces over HTTP]
+    #[arg(long, env)]
+    otlp_endpoint: Option<String>,
+
+    /// Transport of the OTLP exporter
+    #[arg(long, env, value_enum, default_value_t = OtlpProtocol::Grpc)]
+    otlp_protocol: OtlpProtocol,
+
+    /// Ratio of the traces exported, between 0.0 and 1.0
+    #[arg(long, env, default_value_t = 1.0)]
+    trace_sampling_ratio: f64,
+
+    /// Keep the traces sampled by the caller and drop those it did not sample, applying the
+    /// sampling ratio only to traces started by rollup-boost
+    #[arg(long, env, default_value = "false")]
+    trace_parent_based: bool,
+
+    /// Log level
+    #[arg(long, env, default_value = "info")]
+    log_level: Level,
+
+    /// Log format
+    #[arg(long, env, default_value = "text")]
+    log_format: String,
+
+    /// File the logs are appended to, in the log format, on top of stdout
+    #[arg(long, env, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Write the logs to stdout, which can be turned off when they are written to a log file
+    #[arg(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    log_stdout: bool,
+}
+
+impl Args {
+    /// Checks the arguments that clap cannot, reporting every problem at once so the server
+    /// does not fail halfway through startup.
+    fn validate(&self) -> eyre::Result<()> {
+        let mut problems = vec![];
+
+        if socket_addr(&self.rpc_host, self.rpc_port).is_err() {
+            problems.push(format!(
+                "invalid rpc address {}:{}",
+                self.rpc_host, self.rpc_port
+            ));
+        }
+
+        if let Some(public_port) = self.public_rpc_port {
+            let public_host = self.public_rpc_host.as_deref().unwrap_or(&self.rpc_host);
+            if socket_addr(public_host, public_port).is_err() {
+                problems.push(format!(
+                    "invalid public rpc address {}:{}",
+                    public_host, public_port
+                ));
+            }
+        }
+
+        if self.metrics {
+            if socket_addr(&self.metrics_host, self.metrics_port).is_err() {
+                problems.push(format!(
+                    "invalid metrics address {}:{}",
+                    self.metrics_host, self.metrics_port
+                ));
+            }
+            if !self.metrics_path.starts_with('/') {
+                problems.push(format!(
+                    "metrics path {} must start with /",
+                    self.metrics_path
+                ));
+            }
+            if self.metrics_port == self.rpc_port {
+                problems.push(format!(
+                    "rpc and metrics servers both use port {}",
+                    self.rpc_port
+                ));
+            }
+        }
+
+        if !self.log_stdout && self.log_file.is_none() {
+            problems.push("--log-stdout false requires --log-file".to_string());
+        }
+
+        if self.tracing {
+            let endpoint = self.otlp_endpoint();
+            let uri = endpoint
+                .parse::<Uri>()
+                .ok()
+                .filter(|uri| uri.scheme().is_some() && uri.host().is_some());
+            match uri {
+                None => problems.push(format!("invalid otlp endpoint {endpoint}")),
+                Some(uri) if !matches!(uri.scheme_str(), Some("http" | "https")) => problems.push(
+                    format!("otlp endpoint {endpoint} must use the http or https scheme"),
+                ),
+                // A gRPC collector is reached at its address, only HTTP endpoints have a path
+                Some(uri) if self.otlp_protocol == OtlpProtocol::Grpc && uri.path() != "/" => {
+                    problems.push(format!(
+                        "otlp endpoint {endpoint} has a path, which requires --otlp-protocol http"
+                    ))
+                }
+                Some(_) => {}
+            }
+            if !(0.0..=1.0).contains(&self.trace_sampling_ratio) {
+                problems.push(format!(
+                    "trace sampling ratio {} is not between 0.0 and 1.0",
+                    self.trace_sampling_ratio
+                ));
+            }
+        }
+
+        if self.slot_time.is_some()
+            && !(self.builder_deadline_fraction > 0.0 && self.builder_deadline_fraction <= 1.0)
+        {
+            problems.push(format!(
+                "builder deadline fraction {} must be above 0.0 and at most 1.0",
+                self.builder_deadline_fraction
+            ));
+        }
+
+        match (&self.l2_client.l2_jwt_token, &self.l2_client.l2_jwt_path) {
+            (None, None) => problems.push("missing l2 JWT secret".to_string()),
+            (None, Some(path)) if !self.generates_jwt(path) => {
+                check_readable("l2 JWT file", path, &mut problems)
+            }
+            _ => {}
+        }
+        match (
+            &self.builder.builder_jwt_token,
+            &self.builder.builder_jwt_path,
+        ) {
+            (None, None) => problems.push("missing builder JWT secret".to_string()),
+            (None, Some(path)) if !self.generates_jwt(path) => {
+                check_readable("builder JWT file", path, &mut problems)
+            }
+            _ => {}
+        }
+        for builder in &self.builders {
+            check_readable("builder JWT file", &builder.jwt_path, &mut problems);
+        }
+        if let Some(path) = &self.l2_failover_jwt_path {
+            check_readable("l2 failover JWT file", path, &mut problems);
+        }
+        // The l2 and builder urls are checked once the proxy is built
+        let upstreams = self
+            .l2_failover_url
+            .iter()
+            .map(|url| ("l2 failover", url))
+            .chain(
+                self.builders
+                    .iter()
+                    .map(|builder| ("builder", &builder.url)),
+            );
+        for (upstream, url) in upstreams {
+            if let Err(e) = proxy::validate_upstream_uri(upstream, url) {
+                problems.push(e.to_string());
+            }
+        }
+
+        self.check_proxy_loops(&mut problems);
+
+        for path in [
+            &self.tls.tls_ca_bundle,
+            &self.tls.tls_client_cert,
+            &self.tls.tls_client_key,
+            &self.rpc_tls.rpc_tls_cert,
+            &self.rpc_tls.rpc_tls_key,
+            &self.rpc_tls.rpc_client_ca,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            check_readable("TLS file", path, &mut problems);
+        }
+
+        if !problems.is_empty() {
+            bail!("invalid arguments:\n  - {}", problems.join("\n  - "));
+        }
+        Ok(())
+    }
+}
+
+/// Transport used to export traces to the OTLP collector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OtlpProtocol {
+    /// gRPC, usually on port 4317
+    Grpc,
+    /// HTTP with protobuf payloads, usually on port 4318
+    Http,
+}
+
+impl OtlpProtocol {
+    fn default_endpoint(self) -> &'static str {
+        match self {
+            OtlpProtocol::Grpc => "http://localhost:4317",
+            OtlpProtocol::Http => "http://localhost:4318/v1/traces",
+        }
+    }
+}
+
+impl Args {
+    /// Returns the OTLP endpoint, defaulting to the one of the protocol.
+    fn otlp_endpoint(&self) -> &str {
+        self.otlp_endpoint
+            .as_deref()
+            .unwrap_or(self.otlp_protocol.default_endpoint())
+    }
+}
+
+impl Args {
+    /// Returns the methods and prefixes given to the method routing and timeout options, whose
+    /// calls are labelled with their method in the proxy metrics.
+    fn configured_methods(&self) -> Vec<String> {
+        [
+            &self.multiplex_methods,
+            &self.forward_methods,
+            &self.builder_methods,
+        ]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .cloned()
+        .chain(
+            self.method_timeouts
+                .iter()
+                .map(|timeout| timeout.prefix.clone()),
+        )
+        .collect()
+    }
+
+    /// Reports the upstreams that are one of the servers of rollup-boost, which would forward
+    /// requests to itself.
+    fn check_proxy_loops(&self, problems: &mut Vec<String>) {
+        let mut listeners = vec![("rpc", self.rpc_host.as_str(), self.rpc_port)];
+        if let Some(public_port) = self.public_rpc_port {
+            let public_host = self.public_rpc_host.as_deref().unwrap_or(&self.rpc_host);
+            listeners.push(("public rpc", public_host, public_port));
+        }
+        if let Some(ws_port) = self.ws_port {
+            listeners.push(("ws", &self.rpc_host, ws_port));
+        }
+        if self.metrics {
+            listeners.push(("metrics", &self.metrics_host, self.metrics_port));
+        }
+
+        let mut upstreams = vec![
+            ("l2", &self.l2_client.l2_url),
+            ("builder", &self.builder.builder_url),
+        ];
+        upstreams.extend(self.l2_failover_url.iter().map(|url| ("l2 failover", url)));
+        upstreams.extend(
+            self.builders
+                .iter()
+                .map(|builder| ("builder", &builder.url)),
+        );
+
+        for (server, host, port) in listeners {
+            for (upstream, url) in &upstreams {
+                if proxies_to_itself(host, port, url) {
+                    problems.push(format!(
+                        "{upstream} url {url} is the {server} server on {host}:{port}, requests would be forwarded to rollup-boost itself"
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Whether a random JWT secret is written to `path` on startup.
+    fn generates_jwt(&self, path: &Path) -> bool {
+        self.generate_jwt && !path.exists()
+    }
+
+    /// Reads the JWT secret at `path`, first writing a random one there if it is generated.
+    fn load_jwt(&self, name: &str, path: &Path) -> eyre::Result<JwtSecret> {
+        if self.generates_jwt(path) {
+            let secret = JwtSecret::try_create_random(path)?;
+            info!(message = "generated JWT secret", upstream = name, path = %path.display());
+            return Ok(secret);
+        }
+        Ok(JwtSecret::from_file(path)?)
+    }
+}
+
+/// Reads the JWT secrets of the upstreams from their files again. Requests signed from now on
+/// use the new secrets, a file that cannot be read keeps its current secret.
+fn reload_jwt_secrets(jwt_files: &[(PathBuf, SharedSecret)]) {
+    for (path, secret) in jwt_files {
+        match JwtSecret::from_file(path) {
+            Ok(new_secret) => {
+                secret.store(new_secret);
+                info!(message = "reloaded JWT secret", path = %path.display());
+            }
+            Err(e) => warn!(
+                message = "failed to reload JWT secret, keeping the current one",
+                path = %path.display(),
+                error = %e
+            ),
+        }
+    }
+}
+
+/// Returns the address to bind on `host`, an IPv4 or IPv6 address, the latter optionally in
+/// brackets as in `[::]`.
+fn socket_addr(host: &str, port: u16) -> eyre::Result<SocketAddr> {
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    Ok(SocketAddr::new(host.parse::<IpAddr>()?, port))
+}
+
+/// Whether `upstream` is a server listening on `host` and `port`. Only addresses and
+/// `localhost` are compared, other names are not resolved.
+fn proxies_to_itself(host: &str, port: u16, upstream: &Uri) -> bool {
+    let Ok(listen) = socket_addr(host, port).map(|addr| addr.ip()) else {
+        return false;
+    };
+    let upstream_port = upstream.port_u16().or(match upstream.scheme_str() {
+        Some("http") => Some(80),
+        Some("https") => Some(443),
+        _ => None,
+    });
+    let upstream_host = upstream.host().map(|host| {
+        host.strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host)
+    });
+    let Some((upstream_host, upstream_port)) = upstream_host.zip(upstream_port) else {
+        return false;
+    };
+    if upstream_port != port {
+        return false;
+    }
+
+    let upstream_ip = match upstream_host.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) if upstream_host.eq_ignore_ascii_case("localhost") => {
+            return listen.is_loopback() || listen.is_unspecified()
+        }
+        Err(_) => return false,
+    };
+    // A server listening on all interfaces is reached on the loopback one
+    upstream_ip == listen
+        || (listen.is_unspecified() && (upstream_ip.is_loopback() || upstream_ip.is_unspecified()))
+        || (listen.is_loopback() && upstream_ip.is_loopback())
+}
+
+/// Binds a listener on `addr`. An IPv6 listener only accepts IPv6 connections unless
+/// `dual_stack` is set, whatever the system default.
+fn bind_tcp(addr: SocketAddr, dual_stack: bool) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+/// Starts an rpc server on `listener` serving `module`, with the client authentication and the
+/// proxy in front of it. Connections are served over TLS when given a `tls` acceptor.
+fn start_rpc_server(
+    listener: std::net::TcpListener,
+    tls: Option<TlsAcceptor>,
+    max_connections: u32,
+    max_request_size: u32,
+    client_auth: StaticTokenLayer<AuthLayer<SharedJwtValidator>>,
+    proxy_layer: Option<ProxyLayer>,
+    module: RpcModule<()>,
+) -> eyre::Result<ServerHandle> {
+    let builder = Server::builder()
+        .max_connections(max_connections)
+        .max_request_body_size(max_request_size)
+        .set_http_middleware(
+            tower::ServiceBuilder::new()
+                .layer(client_auth)
+                .option_layer(proxy_layer),
+        );
+    let Some(acceptor) = tls else {
+        return Ok(builder.build_from_tcp(listener)?.start(module));
+    };
+
+    let (stop_handle, handle) = stop_channel();
+    let service = builder
+        .to_service_builder()
+        .build(module, stop_handle.clone());
+    tokio::spawn(tls::serve(
+        TcpListener::from_std(listener)?,
+        acceptor,
+        service,
+        stop_handle,
+        max_connections,
+        tls::HANDSHAKE_TIMEOUT,
+    ));
+    Ok(handle)
+}
+
+fn check_readable(name: &str, path: &Path, problems: &mut Vec<String>) {
+    if let Err(e) = std::fs::File::open(path) {
+        problems.push(format!("cannot read {name} {}: {e}", path.display()));
+    }
+}
+
+fn main() -> eyre::Result<()> {
+    // Load .env file. The environment is set before the runtime starts its threads
+    dotenv().ok();
+    let command = Args::command();
+    if let Some(path) = command
+        .clone()
+        .ignore_errors(true)
+        .get_matches()
+        .get_one::<PathBuf>("config")
+    {
+        config::load(&command, path)?;
+    }
+    let args: Args = Args::parse();
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args))
+}
+
+async fn run(args: Args) -> eyre::Result<()> {
+    args.validate()?;
+
+    // Logging and telemetry setup, so the spans of the proxy are exported too. The guard
+    // flushes the log file when main returns
+    let _log_file_guard = init_telemetry(&args)?;
+
+    if let Some(config) = &args.config {
+        info!(message = "loaded config file", path = %config.display());
+    }
+
+    let (metrics, proxy_metrics) = if args.metrics {
+        let recorder = PrometheusBuilder::new()
+            .set_buckets_for_metric(
+                Matcher::Suffix("forward_duration_seconds".to_string()),
+                FORWARD_DURATION_BUCKETS,
+            )?
+            .set_buckets_for_metric(
+                Matcher::Suffix("build_latency_seconds".to_string()),
+                BUILD_LATENCY_BUCKETS,
+            )?
+            .set_buckets_for_metric(
+                Matcher::Suffix("canary_duration_seconds".to_string()),
+                FORWARD_DURATION_BUCKETS,
+            )?
+            .build_recorder();
+        let handle = recorder.handle();
+
+        // Build metrics stack
+        Stack::new(recorder)
+            .push(PrefixLayer::new("rollup-boost"))
+            .install()?;
+        record_build_info(SystemTime::now());
+
+        // Start the metrics server, bound here so a port in use fails the startup
+        let addr = socket_addr(&args.metrics_host, args.metrics_port)?;
+        let listener = bind_tcp(addr, args.dual_stack)
+            .and_then(TcpListener::from_std)
+            .inspect_err(|e| error!(message = "failed to bind metrics server", %addr, error = %e))
+            .wrap_err_with(|| format!("failed to bind metrics server on {addr}"))?;
+        info!("Metrics server running on {}", addr);
+        // Run the metrics server in a separate task
+        tokio::spawn(init_metrics_server(
+            listener,
+            handle,
+            args.metrics_path.clone(),
+            args.metrics_auth_token.clone(),
+        ));
+
+        (
+            Some(Arc::new(ServerMetrics::default())),
+            Some(Arc::new(
+                ProxyMetrics::default().with_methods(args.configured_methods()),
+            )),
+        )
+    } else {
+        (None, None)
+    };
+
+    // Secrets read from a file are shared by the rollup-boost server and the proxy, and read
+    // again on SIGHUP
+    let mut jwt_files = vec![];
+    let l2_auth_jwt = if let Some(secret) = args.l2_client.l2_jwt_token {
+        SharedSecret::new(secret)
+    } else if let Some(path) = args.l2_client.l2_jwt_path.as_ref() {
+        let secret = SharedSecret::new(args.load_jwt("l2", path)?);
+        jwt_files.push((path.clone(), secret.clone()));
+        secret
+    } else {
+        bail!("Missing L2 Client JWT secret");
+    };
+
+    let builder_auth_jwt = if let Some(secret) = args.builder.builder_jwt_token {
+        SharedSecret::new(secret)
+    } else if let Some(path) = args.builder.builder_jwt_path.as_ref() {
+        let secret = SharedSecret::new(args.load_jwt("builder", path)?);
+        jwt_files.push((path.clone(), secret.clone()));
+        secret
+    } else {
+        bail!("Missing Builder JWT secret");
+    };
+
+    let l2_client_args = args.l2_client;
+    let builder_args = args.builder;
+    // Checked before the clients are built, so a misconfigured uri is reported as such
+    let proxy_layer = ProxyLayer::try_new(
+        l2_client_args.l2_url.clone(),
+        l2_auth_jwt.clone(),
+        builder_args.builder_url.clone(),
+        builder_auth_jwt.clone(),
+    )?;
+
+    let l2_client = ExecutionClient::new(
+        l2_client_args.l2_url.clone(),
+        l2_auth_jwt.clone(),
+        l2_client_args.l2_timeout,
+    )?;
+
+    let builder_client = ExecutionClient::new(
+        builder_args.builder_url.clone(),
+        builder_auth_jwt.clone(),
+        builder_args.builder_timeout,
+    )?;
+
+    let mut rollup_boost =
+        RollupBoostServer::new(l2_client, builder_client, args.boost_sync, metrics.clone())
+            .with_admin_api(args.enable_admin_api)
+            .with_builder_payload_timeout(Duration::from_millis(args.builder_payload_timeout))
+            .with_payload_selector(
+                args.selection_strategy
+                    .selector(args.min_builder_value_delta),
+            )
+            .with_builder_shadow_mode(args.builder_shadow_mode)
+            .with_win_rate_window(args.win_rate_window)
+            .with_payload_cache_ttl(Duration::from_millis(args.payload_cache_ttl))
+            .with_builder_sync_retries(
+                args.builder_sync_retries,
+                Duration::from_millis(args.builder_sync_retry_interval),
+            );
+    if let Some(builder_methods) = args.builder_methods.clone() {
+        rollup_boost = rollup_boost.with_builder_methods(builder_methods);
+    }
+    if let Some(slot_time) = args.slot_time {
+        rollup_boost = rollup_boost.with_slot_deadline(SlotDeadline {
+            slot_time: Duration::from_millis(slot_time),
+            fraction: args.builder_deadline_fraction,
+        });
+    }
+    let builder_enabled = rollup_boost.builder_enabled.clone();
+    if let Some(interval) = args.builder_health_check_interval {
+        tokio::spawn(health::monitor_builder(
+            rollup_boost.builder_client.clone(),
+            builder_enabled.clone(),
+            HealthCheckConfig {
+                interval: Duration::from_millis(interval),
+                unhealthy_threshold: args.builder_unhealthy_threshold,
+                healthy_threshold: args.builder_healthy_threshold,
+            },
+        ));
+    }
+    if let Some(interval) = args.canary_interval {
+        tokio::spawn(canary::run_canary(
+            vec![
+                ("l2", rollup_boost.l2_client.clone()),
+                ("builder", rollup_boost.builder_client.clone()),
+            ],
+            Duration::from_millis(interval),
+        ));
+    }
+
+    let subscription_proxy = SubscriptionProxy::new(&l2_client_args.l2_url, l2_auth_jwt.clone());
+
+    if args.builder_only {
+        warn!(
+            message = "builder-only mode is enabled, engine calls go to the builder only and its payloads are not validated by the l2. This is unsafe and for diagnostics only",
+            builder_url = %builder_args.builder_url,
+        );
+    }
+
+    // Build and start the server
+    info!("Starting server on :{}", args.rpc_port);
+
+    let mut proxy_layer = proxy_layer
+        .with_connector(https_connector(
+            &args.tls,
+            ConnectConfig {
+                nodelay: args.tcp_nodelay,
+                keepalive: (args.tcp_keepalive > 0)
+                    .then(|| Duration::from_millis(args.tcp_keepalive)),
+                connect_timeout: (args.connect_timeout > 0)
+                    .then(|| Duration::from_millis(args.connect_timeout)),
+            }
+            .http_connector(),
+            args.upstream_http_version,
+        )?)
+        .with_pool(PoolConfig {
+            max_idle_per_host: args.pool_max_idle_per_host,
+            idle_timeout: Duration::from_millis(args.pool_idle_timeout),
+        })
+        .with_http_version(args.upstream_http_version)
+        .with_builder_enabled(builder_enabled)
+        .with_admin_api(args.enable_admin_api)
+        .with_l2_timeout(Duration::from_millis(l2_client_args.l2_timeout))
+        .with_builder_timeout(Duration::from_millis(builder_args.builder_timeout))
+        .with_max_body_size(args.max_request_size)
+        .with_stream_threshold(args.stream_threshold)
+        .with_max_builder_forwards(args.max_builder_forwards)
+        .with_max_concurrent_requests(args.max_concurrent_requests)
+        .with_response_compression(args.response_compression)
+        .with_healthz_verbose(args.healthz_verbose)
+        .with_forward_raw_txs_to_builder(args.forward_raw_txs_to_builder)
+        .with_forward_bundles_to_l2(args.forward_bundles_to_l2)
+        .with_builder_only(args.builder_only)
+        .with_strict_jsonrpc(args.strict_jsonrpc)
+        .with_method_timeouts(args.method_timeouts)
+        .with_upstream_error_codes(args.upstream_error_codes)
+        .with_builder_headers(args.forward_headers)
+        .with_stripped_headers(args.strip_headers)
+        .with_retry(RetryConfig {
+            max_attempts: args.forward_max_attempts,
+            base_delay: Duration::from_millis(args.forward_retry_base_delay),
+            max_delay: Duration::from_millis(args.forward_retry_max_delay),
+            jitter: args.forward_retry_jitter,
+        })
+        .with_circuit_breaker(CircuitBreakerConfig {
+            failure_threshold: args.builder_circuit_failure_threshold,
+            cooldown: Duration::from_millis(args.builder_circuit_cooldown),
+            probe_jitter: Duration::from_millis(args.builder_circuit_probe_jitter),
+        });
+    if let Some(multiplex_methods) = args.multiplex_methods {
+        proxy_layer = proxy_layer.with_multiplex_methods(multiplex_methods);
+    }
+    if let Some(forward_methods) = args.forward_methods {
+        proxy_layer = proxy_layer.with_forward_methods(forward_methods);
+    }
+    if let Some(builder_methods) = args.builder_methods {
+        proxy_layer = proxy_layer.with_builder_methods(builder_methods);
+    }
+    if args.rate_limit.is_some() || args.engine_rate_limit.is_some() {
+        let config = |requests_per_second: u32, burst: Option<u32>| RateLimitConfig {
+            requests_per_second,
+            burst: burst.unwrap_or(requests_per_second),
+        };
+        proxy_layer = proxy_layer.with_rate_limiter(RateLimiter::new(
+            args.rate_limit
+                .map(|rps| config(rps, args.rate_limit_burst)),
+            args.engine_rate_limit
+                .map(|rps| config(rps, args.engine_rate_limit_burst)),
+        ));
+    }
+    if let Some(path) = &args.record_file {
+        proxy_layer = proxy_layer.with_recorder(Recorder::open(path)?);
+    }
+    if let Some(proxy_metrics) = proxy_metrics {
+        proxy_layer = proxy_layer.with_metrics(proxy_metrics);
+    }
+    for builder in args.builders {
+        let secret = SharedSecret::new(JwtSecret::from_file(&builder.jwt_path)?);
+        jwt_files.push((builder.jwt_path, secret.clone()));
+        proxy_layer = proxy_layer.with_builder(builder.url, secret);
+    }
+    if let Some(url) = args.l2_failover_url {
+        let secret = match args.l2_failover_jwt_path {
+            Some(path) => {
+                let secret = SharedSecret::new(JwtSecret::from_file(&path)?);
+                jwt_files.push((path, secret.clone()));
+                secret
+            }
+            None => l2_auth_jwt.clone(),
+        };
+        info!(message = "failing over to a second l2", url = %url);
+        let cooldown = Duration::from_millis(args.l2_failover_cooldown);
+        let failover_client =
+            ExecutionClient::new(url.clone(), secret.clone(), l2_client_args.l2_timeout)?;
+        rollup_boost = rollup_boost.with_l2_failover(failover_client, cooldown);
+        proxy_layer = proxy_layer.with_l2_failover(url, secret, cooldown);
+    }
+    if args.no_proxy {
+        warn!(
+            "the proxy is disabled, only the engine and admin methods of rollup-boost are served"
+        );
+    } else {
+        rollup_boost = rollup_boost.with_proxy_config(proxy_layer.config());
+    }
+    let module: RpcModule<()> = rollup_boost.try_into()?;
+
+    let mut sighup = unix_signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading JWT secrets");
+            reload_jwt_secrets(&jwt_files);
+        }
+    });
+
+    let builder_forwards = proxy_layer.builder_forwards();
+    let inflight_requests = proxy_layer.inflight_requests();
+    let proxy_layer = (!args.no_proxy).then_some(proxy_layer);
+    // op-node signs its engine calls with the secret it shares with the l2, validated against
+    // the reloaded one after a SIGHUP
+    let client_auth = StaticTokenLayer::new(
+        args.rpc_static_token.as_deref(),
+        AuthLayer::new(SharedJwtValidator::new(l2_auth_jwt.clone()).with_metrics(metrics)),
+    )?;
+
+    let ipc_handle = match &args.rpc_ipc_path {
+        Some(path) => {
+            let listener = ipc::bind(path)?;
+            info!("Starting IPC server on {}", path.display());
+            let (stop_handle, ipc_handle) = stop_channel();
+            let service = Server::builder()
+                .max_request_body_size(args.max_request_size)
+                .set_http_middleware(
+                    tower::ServiceBuilder::new()
+                        .layer(client_auth.clone())
+                        .option_layer(proxy_layer.clone()),
+                )
+                .to_service_builder()
+                .build(module.clone(), stop_handle.clone());
+            tokio::spawn(ipc::serve(listener, service, stop_handle));
+            Some(ipc_handle)
+        }
+        None => None,
+    };
+
+    // Serves the same module, with the proxy refusing engine and admin methods
+    let public_handle = match args.public_rpc_port {
+        Some(public_port) => {
+            let public_host = args.public_rpc_host.as_deref().unwrap_or(&args.rpc_host);
+            info!(
+                "Starting public RPC server on {}:{}",
+                public_host, public_port
+            );
+            Some(start_rpc_server(
+                bind_tcp(socket_addr(public_host, public_port)?, args.dual_stack)?,
+                None,
+                args.max_connections,
+                args.max_request_size,
+                client_auth.clone(),
+                proxy_layer
+                    .clone()
+                    .map(|proxy_layer| proxy_layer.with_engine_api(false)),
+                module.clone(),
+            )?)
+        }
+        None => None,
+    };
+
+    let rpc_tls = rpc_tls_acceptor(&args.rpc_tls)?;
+    if rpc_tls.is_some() {
+        info!(
+            message = "Serving the RPC server over TLS",
+            client_ca = ?args.rpc_tls.rpc_client_ca
+        );
+    }
+    let handle = start_rpc_server(
+        bind_tcp(socket_addr(&args.rpc_host, args.rpc_port)?, args.dual_stack)?,
+        rpc_tls,
+        args.max_connections,
+        args.max_request_size,
+        client_auth.clone(),
+        proxy_layer,
+        module,
+    )?;
+
+    let ws_handle = match args.ws_port {
+        Some(ws_port) => {
+            info!("Starting WS server on :{}", ws_port);
+            let ws_module: RpcModule<()> = subscription_proxy.try_into()?;
+            // Authenticated like the rpc listeners, as subscriptions are relayed with the l2 JWT
+            let ws_server = Server::builder()
+                .ws_only()
+                .max_connections(args.max_connections)
+                .set_http_middleware(tower::ServiceBuilder::new().layer(client_auth))
+                .build_from_tcp(bind_tcp(
+                    socket_addr(&args.rpc_host, ws_port)?,
+                    args.dual_stack,
+                )?)?;
+            Some(ws_server.start(ws_module))
+        }
+        None => None,
+    };
+
+    let stop_handle = handle.clone();
+
+    // Capture SIGINT and SIGTERM
+    let mut sigint = unix_signal(SignalKind::interrupt())?;
+    let mut sigterm = unix_signal(SignalKind::terminate())?;
+
+    let stopping = tokio::select! {
+        _ = handle.clone().stopped() => {
+            // The server has already shut down by itself
+            info!("Server stopped");
+            false
+        }
+        _ = sigint.recv() => {
+            info!("Received SIGINT, shutting down gracefully...");
+            let _ = stop_handle.stop();
+            true
+        }
+        _ = sigterm.recv() => {
+            info!("Received SIGTERM, shutting down gracefully...");
+            let _ = stop_handle.stop();
+            true
+        }
+    };
+
+    // Subscriptions are closed right away, clients subscribe again after a restart
+    if let Some(ws_handle) = ws_handle {
+        let _ = ws_handle.stop();
+    }
+    if let Some(ipc_handle) = ipc_handle {
+        let _ = ipc_handle.stop();
+    }
+
+    // Public calls are drained like the others
+    if let Some(public_handle) = &public_handle {
+        let _ = public_handle.stop();
+    }
+
+    if stopping {
+        // The server stops accepting connections and resolves once in-flight calls complete,
+        // the remaining ones being aborted when the process exits after the timeout
+        let shutdown_timeout = Duration::from_millis(args.shutdown_timeout);
+        info!(
+            message = "waiting for in-flight requests to complete",
+            inflight = inflight_requests.count(),
+            timeout = ?shutdown_timeout
+        );
+        let started_at = std::time::Instant::now();
+        let stopped = async {
+            handle.stopped().await;
+            if let Some(public_handle) = public_handle {
+                public_handle.stopped().await;
+            }
+        };
+        if tokio::time::timeout(shutdown_timeout, stopped)
+            .await
+            .is_err()
+        {
+            warn!(
+                message = "in-flight requests did not complete before the shutdown timeout, aborting them",
+                aborted = inflight_requests.count(),
+                timeout = ?shutdown_timeout
+            );
+        } else {
+            info!(message = "in-flight requests completed", elapsed = ?started_at.elapsed());
+        }
+    }
+
+    // Mirrored requests are only useful to the running payload job
+    builder_forwards.abort_all();
+
+    if args.tracing {
+        // Flush the spans still buffered by the batch exporter
+        tokio::task::spawn_blocking(global::shutdown_tracer_provider).await?;
+    }
+
+    info!("Shutdown complete");
+    Ok(())
+}
+
+/// Samples `ratio` of the traces, or follows the decision of the caller for traces continued
+/// from a `traceparent` when `parent_based` is set.
+fn trace_sampler(ratio: f64, parent_based: bool) -> Sampler {
+    let sampler = Sampler::TraceIdRatioBased(ratio);
+    if parent_based {
+        Sampler::ParentBased(Box::new(sampler))
+    } else {
+        sampler
+    }
+}
+
+/// Builds the OTLP exporter sending traces to `endpoint` over `protocol`.
+fn span_exporter(protocol: OtlpProtocol, endpoint: &str) -> SpanExporterBuilder {
+    match protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(endpoint)
+            .into(),
+        OtlpProtocol::Http => opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(endpoint)
+            .into(),
+    }
+}
+
+/// Sets up the logs, and the export of the spans over OTLP when tracing is enabled. A tracing
+/// provider that fails to initialize leaves the logs as they are, the failure being logged once
+/// they are set up.
+///
+/// Returns the guard of the writer of the log file, which flushes it once dropped.
+fn init_telemetry(args: &Args) -> eyre::Result<Option<WorkerGuard>> {
+    let (tracer_provider, tracing_error) = match args.tracing.then(|| {
+        init_tracing(
+            span_exporter(args.otlp_protocol, args.otlp_endpoint()),
+            trace_sampler(args.trace_sampling_ratio, args.trace_parent_based),
+        )
+    }) {
+        Some(Ok(provider)) => (Some(provider), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let otel_layer = tracer_provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("rollup-boost"))
+    });
+
+    // Written from a background thread, so requests do not wait on the disk
+    let (file_writer, guard) = match &args.log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| eyre::eyre!("failed to open log file {}: {e}", path.display()))?;
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            (Some(writer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let log_level = args.log_level.to_string();
+    let json = args.log_format.to_lowercase() == "json";
+    tracing_subscriber::registry()
+        .with(EnvFilter::new(log_level)) // Set log level
+        .with(args.log_stdout.then(|| fmt_layer(json, std::io::stdout)))
+        .with(file_writer.map(|writer| fmt_layer(json, writer)))
+        .with(otel_layer)
+        .init();
+
+    if let Some(provider) = tracer_provider {
+        let _ = global::set_tracer_provider(provider);
+    }
+    if let Some(e) = tracing_error {
+        error!(message = "failed to initiate tracing provider, spans are not exported", error = %e);
+    }
+    Ok(guard)
+}
+
+/// Formats the logs written to `writer`, as JSON when `json` is set and as text otherwise.
+fn fmt_layer<S, W>(json: bool, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false); // Disable colored logging
+    if json {
+        layer.json().boxed()
+    } else {
+        layer.boxed()
+    }
+}
+
+fn init_tracing(
+    exporter: SpanExporterBuilder,
+    sampler: Sampler,
+) -> Result<TracerProvider, TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(
+            Config::default()
+                .with_sampler(sampler)
+                .with_resource(Resource::new(vec![opentelemetry::KeyValue::new(
+                    "service.name",
+                    "rollup-boost",
+                )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+}
+
+/// Serves the metrics rendered by `handle` on `path`, to clients sending `auth_token` as a
+/// bearer token when it is set.
+async fn init_metrics_server(
+    listener: TcpListener,
+    handle: PrometheusHandle,
+    path: String,
+    auth_token: Option<String>,
+) {
+    let path = Arc::new(path);
+    let authorization = auth_token.map(|token| Arc::new(format!("Bearer {token}")));
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let handle = handle.clone(); // Clone the handle for each connection
+                let path = path.clone();
+                let authorization = authorization.clone();
+                tokio::task::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let authorized = authorization.as_ref().map_or(true, |expected| {
+                            req.headers().get(AUTHORIZATION).is_some_and(|value| {
+                                bool::from(value.as_bytes().ct_eq(expected.as_bytes()))
+                            })
+                        });
+                        let response = if req.uri().path() != path.as_str() {
+                            Response::builder()
+                                .status(StatusCode::NOT_FOUND)
+                                .body(HttpBody::empty())
+                                .unwrap()
+                        } else if !authorized {
+                            Response::builder()
+                                .status(StatusCode::UNAUTHORIZED)
+                                .header(WWW_AUTHENTICATE, "Bearer")
+                                .body(HttpBody::empty())
+                                .unwrap()
+                        } else {
+                            Response::new(HttpBody::from(handle.render()))
+                        };
+                        async { Ok::<_, hyper::Error>(response) }
+                    });
+
+                    let io = TokioIo::new(stream);
+
+                    if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
+                        error!(message = "Error serving metrics connection", error = %err);
+                    }
+                });
+            }
+            Err(e) => {
+                error!(message = "Error accepting connection", error = %e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_cmd::Command;
+    use http::Uri;
+    use jsonrpsee::core::client::ClientT;
+
+    use crate::auth::AuthClientService;
+    use jsonrpsee::http_client::transport::Error as TransportError;
+    use jsonrpsee::http_client::transport::HttpBackend;
+    use jsonrpsee::http_client::HttpClient;
+    use jsonrpsee::RpcModule;
+    use jsonrpsee::{
+        core::ClientError,
+        rpc_params,
+        server::{ServerBuilder, ServerHandle},
+    };
+    use predicates::prelude::*;
+    use reth_rpc_layer::{AuthLayer, JwtAuthValidator, JwtSecret};
+    use std::result::Result;
+    use std::str::FromStr;
+
+    use super::*;
+
+    const AUTH_PORT: u32 = 8550;
+    const AUTH_ADDR: &str = "0.0.0.0";
+    const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+    #[test]
+    fn test_public_rpc_refuses_engine_methods() {
+        use std::io::{Read, Write};
+
+        let (port, public_port) = (8602, 8603);
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args([
+                "--builder-jwt-token",
+                SECRET,
+                "--builder-url",
+                "http://127.0.0.1:8599",
+            ])
+            .args(["--rpc-port", &port.to_string()])
+            .args(["--public-rpc-port", &public_port.to_string()])
+            .spawn()
+            .unwrap();
+
+        let start = std::time::Instant::now();
+        while !healthz(public_port).is_ok_and(|response| response.starts_with("HTTP/1.1 200")) {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "public server did not start"
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"engine_getPayloadV3","params":["0x0000000000000001"]}"#;
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", public_port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+        assert!(response.contains("method not served on this listener"));
+    }
+
+    #[tokio::test]
+    async fn test_ws_requires_auth() -> eyre::Result<()> {
+        use jsonrpsee::ws_client::WsClientBuilder;
+
+        let (port, ws_port) = (8610, 8609);
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args([
+                "--builder-jwt-token",
+                SECRET,
+                "--builder-url",
+                "http://127.0.0.1:8599",
+            ])
+            .args(["--rpc-port", &port.to_string()])
+            .args(["--ws-port", &ws_port.to_string()])
+            .args(["--rpc-static-token", "static-token"])
+            .spawn()?;
+
+        let start = std::time::Instant::now();
+        while std::net::TcpStream::connect(("127.0.0.1", ws_port)).is_err() {
+            if start.elapsed() > Duration::from_secs(10) {
+                child.kill()?;
+                panic!("server did not start");
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let connect = |authorization: Option<&'static str>| {
+            let mut headers = http::HeaderMap::new();
+            if let Some(authorization) = authorization {
+                headers.insert(AUTHORIZATION, http::HeaderValue::from_static(authorization));
+            }
+            WsClientBuilder::default()
+                .set_headers(headers)
+                .build(format!("ws://127.0.0.1:{ws_port}"))
+        };
+
+        // The handshake is refused without credentials
+        let unauthenticated = connect(None).await;
+        let with_token = connect(Some("Bearer static-token")).await;
+        child.kill()?;
+        child.wait()?;
+        assert!(unauthenticated.is_err());
+        assert!(with_token.is_ok(), "{:?}", with_token.err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_no_proxy() -> eyre::Result<()> {
+        let port = 8606;
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args([
+                "--builder-jwt-token",
+                SECRET,
+                "--builder-url",
+                "http://127.0.0.1:8599",
+            ])
+            .args(["--rpc-port", &port.to_string()])
+            .args(["--no-proxy", "--enable-admin-api"])
+            .spawn()?;
+
+        let secret = SharedSecret::new(JwtSecret::from_hex(SECRET)?);
+        let client = HttpClient::builder()
+            .set_http_middleware(
+                tower::ServiceBuilder::new().layer(auth::AuthClientLayer::new(secret)),
+            )
+            .build(format!("http://127.0.0.1:{port}"))?;
+        let start = std::time::Instant::now();
+        let config = loop {
+            match client
+                .request::<serde_json::Value, _>("admin_rollupBoostConfig", rpc_params![])
+                .await
+            {
+                Ok(config) => break config,
+                Err(_) if start.elapsed() < Duration::from_secs(10) => {
+                    tokio::time::sleep(Duration::from_millis(50)).await
+                }
+                Err(e) => panic!("server did not start: {e}"),
+            }
+        };
+        // Answered by the rollup-boost server, with no proxy in front of it
+        assert_eq!(config["proxy"], serde_json::Value::Null);
+        assert_eq!(config["builderEnabled"], true);
+
+        // Methods of the l2 are not forwarded
+        let error = client
+            .request::<serde_json::Value, _>("eth_blockNumber", rpc_params![])
+            .await
+            .unwrap_err();
+        child.kill()?;
+        child.wait()?;
+        assert!(
+            matches!(&error, ClientError::Call(error) if error.code() == -32601),
+            "{error}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_otlp_endpoint() -> eyre::Result<()> {
+        let port = 8607;
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args([
+                "--builder-jwt-token",
+                SECRET,
+                "--builder-url",
+                "http://127.0.0.1:8599",
+            ])
+            .args(["--rpc-port", &port.to_string(), "--enable-admin-api"])
+            // Nothing listens on the collector port
+            .args(["--tracing", "--otlp-endpoint", "http://127.0.0.1:9"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()?;
+
+        let secret = SharedSecret::new(JwtSecret::from_hex(SECRET)?);
+        let client = HttpClient::builder()
+            .set_http_middleware(
+                tower::ServiceBuilder::new().layer(auth::AuthClientLayer::new(secret)),
+            )
+            .build(format!("http://127.0.0.1:{port}"))?;
+        let start = std::time::Instant::now();
+        let config = loop {
+            match client
+                .request::<serde_json::Value, _>("admin_rollupBoostConfig", rpc_params![])
+                .await
+            {
+                Ok(config) => break config,
+                Err(_) if start.elapsed() < Duration::from_secs(10) => {
+                    tokio::time::sleep(Duration::from_millis(50)).await
+                }
+                Err(e) => panic!("server did not start: {e}"),
+            }
+        };
+        assert_eq!(config["builderEnabled"], true);
+
+        child.kill()?;
+        let output = child.wait_with_output()?;
+        let logs = String::from_utf8(output.stdout)?;
+        // Logged as text, without the collector
+        assert!(
+            logs.lines()
+                .any(|line| line.ends_with(&format!("Starting server on :{port}"))),
+            "{logs}"
+        );
+        assert!(
+            !logs.contains("failed to initiate tracing provider"),
+            "{logs}"
+        );
+        assert!(logs.lines().all(|line| !line.starts_with('{')), "{logs}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_proxy_conflicts_with_rate_limits() {
+        // The calls are limited by the proxy, so they would not be without it
+        for flag in ["--rate-limit", "--engine-rate-limit"] {
+            run_with_args(&["--no-proxy", flag, "10"])
+                .failure()
+                .stderr(predicate::str::contains(format!(
+                    "the argument '--no-proxy' cannot be used with '{flag} <RPS>'"
+                )));
+        }
+    }
+
+    #[test]
+    fn test_invalid_failover_url() {
+        run_with_args(&["--l2-failover-url", "127.0.0.1:8551"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "invalid l2 failover uri `127.0.0.1:8551`: missing scheme",
+            ));
+    }
+
+    #[test]
+    fn test_invalid_builder_url() {
+        run_with_args(&["--builder", "ws://127.0.0.1:8551,/dev/null"])
+            .failure()
+            .stderr(predicate::str::contains("invalid builder uri"))
+            .stderr(predicate::str::contains("scheme must be http or https"));
+    }
+
+    #[test]
+    fn test_no_proxy_conflicts_with_static_token() {
+        // Static-token requests are refused the engine methods by the proxy, they would reach
+        // them without it
+        run_with_args(&["--no-proxy", "--rpc-static-token", "static-token"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "the argument '--no-proxy' cannot be used with '--rpc-static-token <TOKEN>'",
+            ));
+    }
+
+    #[test]
+    fn test_no_proxy_conflicts_with_public_rpc() {
+        run_with_args(&["--no-proxy", "--public-rpc-port", "8605"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "the argument '--no-proxy' cannot be used with '--public-rpc-port <PUBLIC_RPC_PORT>'",
+            ));
+    }
+
+    #[test]
+    fn test_log_file() {
+        let port = 8608;
+        let path = std::env::temp_dir().join(format!("rollup-boost-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args([
+                "--builder-jwt-token",
+                SECRET,
+                "--builder-url",
+                "http://127.0.0.1:8599",
+            ])
+            .args(["--rpc-port", &port.to_string(), "--log-format", "json"])
+            .arg("--log-file")
+            .arg(&path)
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let start = std::time::Instant::now();
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "server did not start"
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let status = std::process::Command::new("kill")
+            .args(["-TERM", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        // Flushed on shutdown, in the log format
+        let logs = std::fs::read_to_string(&path).unwrap();
+        let messages = logs
+            .lines()
+            .map(|line| {
+                let log: serde_json::Value = serde_json::from_str(line).unwrap();
+                log["fields"]["message"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            messages.contains(&format!("Starting server on :{port}")),
+            "{logs}"
+        );
+        assert_eq!(
+            messages.last().map(String::as_str),
+            Some("Shutdown complete")
+        );
+        // and still written to stdout
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout.lines().count(), logs.lines().count(), "{stdout}");
+        assert!(stdout.contains("Shutdown complete"), "{stdout}");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_log_stdout_requires_log_file() {
+        run_with_args(&["--log-stdout", "false"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "--log-stdout false requires --log-file",
+            ));
+    }
+
+    #[test]
+    fn test_exits_cleanly_on_sigterm() {
+        let port = 8597;
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args([
+                "--builder-jwt-token",
+                SECRET,
+                "--builder-url",
+                "http://127.0.0.1:8599",
+            ])
+            .args(["--rpc-port", &port.to_string()])
+            .spawn()
+            .unwrap();
+
+        // Wait for the server to accept connections
+        let start = std::time::Instant::now();
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "server did not start"
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let status = std::process::Command::new("kill")
+            .args(["-TERM", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let start = std::time::Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if start.elapsed() > Duration::from_secs(10) {
+                child.kill().unwrap();
+                panic!("process did not exit after SIGTERM");
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn test_drains_inflight_requests_on_sigterm() {
+        let (port, l2_port) = (8604, 8605);
+        let l2_addr = format!("127.0.0.1:{l2_port}");
+        let mut module = RpcModule::new(());
+        module
+            .register_async_method("greet_melkor", |_, _, _| async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                "You are the dark lord"
+            })
+            .unwrap();
+        let l2 = ServerBuilder::default()
+            .set_http_middleware(tower::ServiceBuilder::default().layer(AuthLayer::new(
+                JwtAuthValidator::new(JwtSecret::from_hex(SECRET).unwrap()),
+            )))
+            .build(l2_addr.parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap()
+            .start(module);
+
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args(["--l2-jwt-token", SECRET])
+            .args(["--l2-url", &format!("http://{l2_addr}")])
+            .args(["--l2-timeout", "5000"])
+            .args(["--builder-jwt-token", SECRET])
+            .args(["--builder-url", "http://127.0.0.1:8599"])
+            .args(["--rpc-port", &port.to_string()])
+            .args(["--shutdown-timeout", "5000"])
+            .spawn()
+            .unwrap();
 
-    /// Use the proposer to sync the builder node
-    #[arg(long, env, default_value = "false")]
-    boost_sync: bool,
+        let start = std::time::Instant::now();
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            if start.elapsed() > Duration::from_secs(10) {
+                child.kill().unwrap();
+                panic!("server did not start");
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
 
-    /// Host to run the server on
-    #[arg(long, env, default_value = "0.0.0.0")]
-    rpc_host: String,
+        let client = HttpClient::builder()
+            .build(format!("http://127.0.0.1:{port}"))
+            .unwrap();
+        let slow_request = tokio::spawn(async move {
+            client
+                .request::<String, _>("greet_melkor", rpc_params![])
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
 
-    /// Port to run the server on
-    #[arg(long, env, default_value = "8081")]
-    rpc_port: u16,
+        let status = std::process::Command::new("kill")
+            .args(["-TERM", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
 
-    // Enable tracing
-    #[arg(long, env, default_value = "false")]
-    tracing: bool,
+        // New connections are refused while the slow request is still being handled
+        let start = std::time::Instant::now();
+        while healthz(port).is_ok() {
+            if start.elapsed() > Duration::from_millis(500) {
+                child.kill().unwrap();
+                panic!("server still accepts connections");
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!slow_request.is_finished());
 
-    // Enable Prometheus metrics
-    #[arg(long, env, default_value = "false")]
-    metrics: bool,
+        assert_eq!(
+            slow_request.await.unwrap().unwrap(),
+            "You are the dark lord"
+        );
+        let start = std::time::Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if start.elapsed() > Duration::from_secs(10) {
+                child.kill().unwrap();
+                panic!("process did not exit after draining");
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        assert!(status.success());
+        l2.stop().unwrap();
+    }
 
-    /// Host to run the metrics server on
-    #[arg(long, env, default_value = "0.0.0.0")]
-    metrics_host: String,
+    #[test]
+    fn test_parse_builder_endpoint() {
+        let endpoint: BuilderEndpoint = "http://localhost:8551,/etc/builder.jwt".parse().unwrap();
+        assert_eq!(endpoint.url, Uri::from_static("http://localhost:8551"));
+        assert_eq!(
+            endpoint.jwt_path,
+            std::path::PathBuf::from("/etc/builder.jwt")
+        );
 
-    /// Port to run the metrics server on
-    #[arg(long, env, default_value = "9090")]
-    metrics_port: u16,
+        assert!("http://localhost:8551".parse::<BuilderEndpoint>().is_err());
+    }
 
-    /// OTLP endpoint
-    #[arg(long, env, default_value = "http://localhost:4317")]
-    otlp_endpoint: String,
+    /// Runs the binary with valid upstreams followed by `args`.
+    fn run_with_args(args: &[&str]) -> assert_cmd::assert::Assert {
+        let mut cmd = Command::cargo_bin("rollup-boost").unwrap();
+        cmd.args([
+            "--l2-jwt-token",
+            SECRET,
+            "--l2-url",
+            "http://127.0.0.1:8598",
+        ])
+        .args([
+            "--builder-jwt-token",
+            SECRET,
+            "--builder-url",
+            "http://127.0.0.1:8599",
+        ])
+        .args(args)
+        .timeout(Duration::from_secs(10));
+        cmd.assert()
+    }
 
-    /// Log level
-    #[arg(long, env, default_value = "info")]
-    log_level: Level,
+    #[test]
+    fn test_invalid_rpc_address() {
+        run_with_args(&["--rpc-host", "not a host"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "invalid rpc address not a host:8081",
+            ));
+    }
 
-    /// Log format
-    #[arg(long, env, default_value = "text")]
-    log_format: String,
-}
+    #[test]
+    fn test_colliding_ports() {
+        run_with_args(&["--metrics", "--rpc-port", "9191", "--metrics-port", "9191"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "rpc and metrics servers both use port 9191",
+            ));
+    }
 
-#[tokio::main]
-async fn main() -> eyre::Result<()> {
-    // Load .env file
-    dotenv().ok();
-    let args: Args = Args::parse();
+    #[test]
+    fn test_builder_only_conflicts_with_shadow_mode() {
+        run_with_args(&["--builder-only", "--builder-shadow-mode"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "the argument '--builder-only' cannot be used with '--builder-shadow-mode'",
+            ));
+    }
 
-    // Initialize logging
-    let log_format = args.log_format.to_lowercase();
-    let log_level = args.log_level.to_string();
-    if log_format == "json" {
-        // JSON log format
-        tracing_subscriber::fmt()
-            .json() // Use JSON format
-            .with_env_filter(EnvFilter::new(log_level)) // Set log level
-            .with_ansi(false) // Disable colored logging
-            .init();
-    } else {
-        // Default (text) log format
-        tracing_subscriber::fmt()
-            .with_env_filter(EnvFilter::new(log_level)) // Set log level
-            .with_ansi(false) // Disable colored logging
-            .init();
+    #[test]
+    fn test_proxy_loop() {
+        run_with_args(&["--rpc-port", "8598"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "l2 url http://127.0.0.1:8598/ is the rpc server on 0.0.0.0:8598, requests would be forwarded to rollup-boost itself",
+            ));
+        run_with_args(&["--metrics", "--metrics-port", "8599"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "builder url http://127.0.0.1:8599/ is the metrics server on",
+            ));
+    }
+
+    #[test]
+    fn test_proxies_to_itself() {
+        let proxies = |host: &str, port: u16, upstream: &str| {
+            proxies_to_itself(host, port, &upstream.parse().unwrap())
+        };
+        assert!(proxies("0.0.0.0", 8551, "http://127.0.0.1:8551"));
+        assert!(proxies("0.0.0.0", 8551, "http://localhost:8551"));
+        assert!(proxies("127.0.0.1", 8551, "127.0.0.1:8551"));
+        assert!(proxies("[::]", 8551, "http://[::1]:8551"));
+        assert!(proxies("10.0.0.1", 8551, "http://10.0.0.1:8551"));
+        assert!(proxies("0.0.0.0", 80, "http://localhost"));
+
+        assert!(!proxies("0.0.0.0", 8081, "http://127.0.0.1:8551"));
+        assert!(!proxies("10.0.0.1", 8551, "http://10.0.0.2:8551"));
+        assert!(!proxies("127.0.0.1", 8551, "http://10.0.0.1:8551"));
+        // Names are not resolved
+        assert!(!proxies("0.0.0.0", 8551, "http://op-geth:8551"));
+        assert!(!proxies("0.0.0.0", 8551, "localhost"));
+    }
+
+    #[test]
+    fn test_metrics_port_in_use() {
+        let _listener = std::net::TcpListener::bind("127.0.0.1:8601").unwrap();
+        run_with_args(&[
+            "--metrics",
+            "--metrics-host",
+            "127.0.0.1",
+            "--metrics-port",
+            "8601",
+        ])
+        .failure()
+        .stderr(predicate::str::contains(
+            "failed to bind metrics server on 127.0.0.1:8601",
+        ));
     }
 
-    let metrics = if args.metrics {
+    #[test]
+    fn test_unreadable_jwt_file() {
+        let mut cmd = Command::cargo_bin("rollup-boost").unwrap();
+        cmd.args([
+            "--l2-jwt-path",
+            "/nonexistent/l2.jwt",
+            "--l2-url",
+            "http://127.0.0.1:8598",
+        ])
+        .args(["--builder-url", "http://127.0.0.1:8599"])
+        .timeout(Duration::from_secs(10));
+
+        // Every problem is reported at once
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "cannot read l2 JWT file /nonexistent/l2.jwt",
+            ))
+            .stderr(predicate::str::contains("missing builder JWT secret"));
+    }
+
+    /// Sends `GET /healthz` on a new connection and returns the raw response, empty if the
+    /// connection was closed without one.
+    fn healthz(port: u16) -> std::io::Result<String> {
+        healthz_at(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    /// Like [healthz], connecting to `addr`.
+    fn healthz_at(addr: SocketAddr) -> std::io::Result<String> {
+        use std::io::{Read, Write};
+
+        let mut stream = std::net::TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn test_metrics_server() -> eyre::Result<()> {
         let recorder = PrometheusBuilder::new().build_recorder();
+        ::metrics::with_local_recorder(&recorder, || ::metrics::gauge!("up").set(1.0));
         let handle = recorder.handle();
 
-        // Build metrics stack
-        Stack::new(recorder)
-            .push(PrefixLayer::new("rollup-boost"))
-            .install()?;
+        let serve = |path: &str, auth_token: Option<&str>| {
+            let handle = handle.clone();
+            let (path, auth_token) = (path.to_string(), auth_token.map(str::to_string));
+            async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let addr = listener.local_addr()?;
+                tokio::spawn(init_metrics_server(listener, handle, path, auth_token));
+                eyre::Ok(addr)
+            }
+        };
+        let client = reqwest::Client::new();
+        let get = |addr: SocketAddr, path: &str, token: Option<&str>| {
+            let mut request = client.get(format!("http://{addr}{path}"));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            async move {
+                let response = request.send().await?;
+                eyre::Ok((response.status().as_u16(), response.text().await?))
+            }
+        };
 
-        // Start the metrics server
-        let metrics_addr = format!("{}:{}", args.metrics_host, args.metrics_port);
-        let addr: SocketAddr = metrics_addr.parse()?;
-        tokio::spawn(init_metrics_server(addr, handle)); // Run the metrics server in a separate task
+        // A custom path replaces /metrics
+        let addr = serve("/internal/metrics", None).await?;
+        let (status, body) = get(addr, "/internal/metrics", None).await?;
+        assert_eq!(status, 200);
+        assert!(body.contains("up 1"));
+        assert_eq!(get(addr, "/metrics", None).await?.0, 404);
 
-        Some(Arc::new(ServerMetrics::default()))
-    } else {
-        None
-    };
+        // With a token, only requests carrying it are served
+        let addr = serve("/metrics", Some("s3cret")).await?;
+        let (status, body) = get(addr, "/metrics", Some("s3cret")).await?;
+        assert_eq!(status, 200);
+        assert!(body.contains("up 1"));
+        assert_eq!(get(addr, "/metrics", None).await?.0, 401);
+        assert_eq!(get(addr, "/metrics", Some("wrong")).await?.0, 401);
+        assert_eq!(get(addr, "/other", Some("s3cret")).await?.0, 404);
 
-    // Telemetry setup
-    if args.tracing {
-        init_tracing(&args.otlp_endpoint);
+        Ok(())
     }
 
-    let l2_client_args = args.l2_client;
+    #[test]
+    fn test_socket_addr() {
+        assert_eq!(
+            socket_addr("0.0.0.0", 8081).unwrap(),
+            "0.0.0.0:8081".parse().unwrap()
+        );
+        assert_eq!(
+            socket_addr("::", 8081).unwrap(),
+            "[::]:8081".parse().unwrap()
+        );
+        assert_eq!(
+            socket_addr("[::1]", 8081).unwrap(),
+            "[::1]:8081".parse().unwrap()
+        );
+        assert!(socket_addr("localhost", 8081).is_err());
+        assert!(socket_addr("[::1", 8081).is_err());
+    }
 
-    let l2_auth_jwt = if let Some(secret) = l2_client_args.l2_jwt_token {
-        secret
-    } else if let Some(path) = l2_client_args.l2_jwt_path.as_ref() {
-        JwtSecret::from_file(path)?
-    } else {
-        bail!("Missing L2 Client JWT secret");
-    };
+    #[test]
+    fn test_dual_stack() {
+        let listener = bind_tcp("[::]:0".parse().unwrap(), true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::net::TcpStream::connect(("::1", port)).unwrap();
+        std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
 
-    let l2_client = ExecutionClient::new(
-        l2_client_args.l2_url.clone(),
-        l2_auth_jwt,
-        l2_client_args.l2_timeout,
-    )?;
+        // Without it an IPv6 listener is not reachable over IPv4
+        let listener = bind_tcp("[::]:0".parse().unwrap(), false).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::net::TcpStream::connect(("::1", port)).unwrap();
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
 
-    let builder_args = args.builder;
-    let builder_auth_jwt = if let Some(secret) = builder_args.builder_jwt_token {
-        secret
-    } else if let Some(path) = builder_args.builder_jwt_path.as_ref() {
-        JwtSecret::from_file(path)?
-    } else {
-        bail!("Missing Builder JWT secret");
-    };
+    #[test]
+    fn test_ipv6_rpc_host() {
+        let port = 8600;
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args(["--builder-jwt-token", SECRET])
+            .args(["--builder-url", "http://127.0.0.1:8599"])
+            .args(["--rpc-host", "::1", "--rpc-port", &port.to_string()])
+            .spawn()
+            .unwrap();
 
-    let builder_client = ExecutionClient::new(
-        builder_args.builder_url.clone(),
-        builder_auth_jwt,
-        builder_args.builder_timeout,
-    )?;
+        let addr = SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, port));
+        let start = std::time::Instant::now();
+        let response = loop {
+            match healthz_at(addr) {
+                Ok(response) => break response,
+                Err(_) if start.elapsed() < Duration::from_secs(10) => {
+                    std::thread::sleep(Duration::from_millis(50))
+                }
+                Err(e) => {
+                    child.kill().unwrap();
+                    panic!("server did not accept the connection: {e}");
+                }
+            }
+        };
 
-    let rollup_boost = RollupBoostServer::new(l2_client, builder_client, args.boost_sync, metrics);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    }
 
-    let module: RpcModule<()> = rollup_boost.try_into()?;
+    #[test]
+    fn test_max_connections() {
+        let port = 8590;
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args([
+                "--l2-jwt-token",
+                SECRET,
+                "--l2-url",
+                "http://127.0.0.1:8598",
+            ])
+            .args(["--builder-jwt-token", SECRET])
+            .args(["--builder-url", "http://127.0.0.1:8599"])
+            .args(["--rpc-port", &port.to_string(), "--max-connections", "1"])
+            .spawn()
+            .unwrap();
+        let is_ok = |response: std::io::Result<String>| {
+            response.is_ok_and(|response| response.starts_with("HTTP/1.1 200"))
+        };
+        let wait_for_ok = |child: &mut std::process::Child| {
+            let start = std::time::Instant::now();
+            while !is_ok(healthz(port)) {
+                if start.elapsed() > Duration::from_secs(10) {
+                    child.kill().unwrap();
+                    panic!("server did not accept the connection");
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        };
+        wait_for_ok(&mut child);
 
-    // Build and start the server
-    info!("Starting server on :{}", args.rpc_port);
+        // While a connection is open the next one is refused
+        std::thread::sleep(Duration::from_millis(100));
+        let open = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let refused = !is_ok(healthz(port));
 
-    let service_builder = tower::ServiceBuilder::new().layer(ProxyLayer::new(
-        l2_client_args.l2_url,
-        l2_auth_jwt,
-        builder_args.builder_url,
-        builder_auth_jwt,
-    ));
+        // and accepted again once it closes
+        drop(open);
+        wait_for_ok(&mut child);
 
-    let server = Server::builder()
-        .set_http_middleware(service_builder)
-        .build(format!("{}:{}", args.rpc_host, args.rpc_port).parse::<SocketAddr>()?)
-        .await?;
-    let handle = server.start(module);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(refused, "a connection over the limit was accepted");
+    }
 
-    let stop_handle = handle.clone();
+    #[tokio::test]
+    async fn test_generate_jwt() {
+        let dir = std::env::temp_dir().join(format!("rollup-boost-jwt-{}", std::process::id()));
+        let path = dir.join("engine.jwt");
+        let _ = std::fs::remove_dir_all(&dir);
+        let (port, l2_port) = (8596, 8595);
 
-    // Capture SIGINT and SIGTERM
-    let mut sigint = unix_signal(SignalKind::interrupt())?;
-    let mut sigterm = unix_signal(SignalKind::terminate())?;
+        // Both upstreams share the generated secret
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .arg("--generate-jwt")
+            .args(["--l2-jwt-path", path.to_str().unwrap()])
+            .args(["--l2-url", &format!("http://127.0.0.1:{l2_port}")])
+            .args(["--builder-jwt-path", path.to_str().unwrap()])
+            .args(["--builder-url", "http://127.0.0.1:8594"])
+            .args(["--rpc-port", &port.to_string()])
+            .spawn()
+            .unwrap();
 
-    tokio::select! {
-        _ = handle.stopped() => {
-            // The server has already shut down by itself
-            info!("Server stopped");
-        }
-        _ = sigint.recv() => {
-            info!("Received SIGINT, shutting down gracefully...");
-            let _ = stop_handle.stop();
-        }
-        _ = sigterm.recv() => {
-            info!("Received SIGTERM, shutting down gracefully...");
-            let _ = stop_handle.stop();
+        let start = std::time::Instant::now();
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            if start.elapsed() > Duration::from_secs(10) {
+                child.kill().unwrap();
+                panic!("server did not start");
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
         }
+
+        let secret = JwtSecret::from_file(&path).unwrap();
+        let l2 = spawn_server_with(secret, &format!("127.0.0.1:{l2_port}")).await;
+        let client = HttpClient::builder()
+            .build(format!("http://127.0.0.1:{port}"))
+            .unwrap();
+        let response = client
+            .request::<String, _>("greet_melkor", rpc_params![])
+            .await;
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        l2.stop().unwrap();
+        l2.stopped().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(response.unwrap(), "You are the dark lord");
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_reload_jwt_on_sighup() {
+        let path =
+            std::env::temp_dir().join(format!("rollup-boost-reload-{}.jwt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let old_secret = JwtSecret::try_create_random(&path).unwrap();
+        let (port, l2_port) = (8593, 8592);
+        let l2_addr = format!("127.0.0.1:{l2_port}");
 
-fn init_tracing(endpoint: &str) {
-    global::set_text_map_propagator(TraceContextPropagator::new());
-    let provider = opentelemetry_otlp::new_pipeline()
-        .tracing()
-        .with_exporter(
-            opentelemetry_otlp::new_exporter()
-                .tonic()
-                .with_endpoint(endpoint),
-        )
-        .with_trace_config(Config::default().with_resource(Resource::new(vec![
-            opentelemetry::KeyValue::new("service.name", "rollup-boost"),
-        ])))
-        .install_batch(opentelemetry_sdk::runtime::Tokio);
-    match provider {
-        Ok(provider) => {
-            let _ = global::set_tracer_provider(provider);
-        }
-        Err(e) => {
-            error!(message = "failed to initiate tracing provider", "error" = %e);
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("rollup-boost"))
+            .args(["--l2-jwt-path", path.to_str().unwrap()])
+            .args(["--l2-url", &format!("http://{l2_addr}")])
+            .args(["--builder-jwt-token", SECRET])
+            .args(["--builder-url", "http://127.0.0.1:8591"])
+            .args(["--rpc-port", &port.to_string()])
+            // Clients without the static token are authenticated with the l2 secret
+            .args(["--rpc-static-token", "static-token"])
+            .spawn()
+            .unwrap();
+
+        let start = std::time::Instant::now();
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            if start.elapsed() > Duration::from_secs(10) {
+                child.kill().unwrap();
+                panic!("server did not start");
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
         }
-    }
-}
+        let client_with = |secret: SharedSecret| {
+            HttpClient::builder()
+                .set_http_middleware(
+                    tower::ServiceBuilder::new().layer(auth::AuthClientLayer::new(secret)),
+                )
+                .build(format!("http://127.0.0.1:{port}"))
+                .unwrap()
+        };
+        // Signs like op-node, with the secret it shares with the l2
+        let client_secret = SharedSecret::new(old_secret);
+        let client = client_with(client_secret.clone());
 
-async fn init_metrics_server(addr: SocketAddr, handle: PrometheusHandle) -> eyre::Result<()> {
-    let listener = TcpListener::bind(addr).await?;
-    info!("Metrics server running on {}", addr);
+        let l2 = spawn_server_with(old_secret, &l2_addr).await;
+        let response = client
+            .request::<String, _>("greet_melkor", rpc_params![])
+            .await;
+        assert_eq!(response.unwrap(), "You are the dark lord");
+        l2.stop().unwrap();
+        l2.stopped().await;
 
-    loop {
-        match listener.accept().await {
-            Ok((stream, _)) => {
-                let handle = handle.clone(); // Clone the handle for each connection
-                tokio::task::spawn(async move {
-                    let service = service_fn(move |_req: Request<hyper::body::Incoming>| {
-                        let response = match _req.uri().path() {
-                            "/metrics" => Response::new(HttpBody::from(handle.render())),
-                            _ => Response::builder()
-                                .status(StatusCode::NOT_FOUND)
-                                .body(HttpBody::empty())
-                                .unwrap(),
-                        };
-                        async { Ok::<_, hyper::Error>(response) }
-                    });
+        // The proxy signs with the old secret until it is told to reload the file
+        std::fs::remove_file(&path).unwrap();
+        let new_secret = JwtSecret::try_create_random(&path).unwrap();
+        let l2 = spawn_server_with(new_secret, &l2_addr).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client
+            .request::<String, _>("greet_melkor", rpc_params![])
+            .await;
+        assert!(matches!(
+            response.unwrap_err(),
+            ClientError::Transport(e)
+                if matches!(e.downcast_ref::<TransportError>(), Some(TransportError::Rejected { status_code: 401 }))
+        ));
 
-                    let io = TokioIo::new(stream);
+        let status = std::process::Command::new("kill")
+            .args(["-HUP", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
 
-                    if let Err(err) = http1::Builder::new().serve_connection(io, service).await {
-                        error!(message = "Error serving metrics connection", error = %err);
-                    }
-                });
+        // Once reloaded, the client signing with the new secret is let through and the proxy
+        // signs with it too
+        client_secret.store(new_secret);
+        let start = std::time::Instant::now();
+        let response = loop {
+            match client
+                .request::<String, _>("greet_melkor", rpc_params![])
+                .await
+            {
+                Ok(response) => break response,
+                Err(_) if start.elapsed() < Duration::from_secs(10) => {
+                    tokio::time::sleep(Duration::from_millis(50)).await
+                }
+                Err(e) => panic!("the new secret was not used: {e}"),
             }
-            Err(e) => {
-                error!(message = "Error accepting connection", error = %e);
-            }
-        }
+        };
+        // while one still signing with the old secret is rejected
+        let response_with_old_secret = client_with(SharedSecret::new(old_secret))
+            .request::<String, _>("greet_melkor", rpc_params![])
+            .await;
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        l2.stop().unwrap();
+        l2.stopped().await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(response, "You are the dark lord");
+        assert!(matches!(
+            response_with_old_secret.unwrap_err(),
+            ClientError::Transport(e)
+                if matches!(e.downcast_ref::<TransportError>(), Some(TransportError::Rejected { status_code: 401 }))
+        ));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use assert_cmd::Command;
-    use http::Uri;
-    use jsonrpsee::core::client::ClientT;
+    #[test]
+    fn test_jwt_secret_sources() {
+        let path = std::env::temp_dir().join("rollup-boost-test-l2.jwt");
+        std::fs::write(&path, SECRET).unwrap();
+        let path = path.to_str().unwrap();
 
-    use jsonrpsee::http_client::transport::Error as TransportError;
-    use jsonrpsee::http_client::transport::HttpBackend;
-    use jsonrpsee::http_client::HttpClient;
-    use jsonrpsee::RpcModule;
-    use jsonrpsee::{
-        core::ClientError,
-        rpc_params,
-        server::{ServerBuilder, ServerHandle},
-    };
-    use predicates::prelude::*;
-    use reth_rpc_layer::{AuthClientService, AuthLayer, JwtAuthValidator, JwtSecret};
-    use std::result::Result;
-    use std::str::FromStr;
+        // A secret read from a file and one given inline pass validation, so startup only fails
+        // on the rpc address
+        Command::cargo_bin("rollup-boost")
+            .unwrap()
+            .args(["--l2-jwt-path", path, "--l2-url", "http://127.0.0.1:8598"])
+            .args(["--builder-jwt-token", SECRET])
+            .args(["--builder-url", "http://127.0.0.1:8599"])
+            .args(["--rpc-host", "not a host"])
+            .timeout(Duration::from_secs(10))
+            .assert()
+            .failure()
+            .stderr(
+                predicate::str::contains("invalid rpc address")
+                    .and(predicate::str::contains("JWT").not()),
+            );
 
-    use super::*;
+        // But a secret cannot be given both ways
+        run_with_args(&["--l2-jwt-path", path])
+            .failure()
+            .stderr(predicate::str::contains(
+                "the argument '--l2-jwt-token <HEX>' cannot be used with '--l2-jwt-path <PATH>'",
+            ));
 
-    const AUTH_PORT: u32 = 8550;
-    const AUTH_ADDR: &str = "0.0.0.0";
-    const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_span_exporter() {
+        assert!(matches!(
+            span_exporter(OtlpProtocol::Grpc, OtlpProtocol::Grpc.default_endpoint()),
+            SpanExporterBuilder::Tonic(_)
+        ));
+        assert!(matches!(
+            span_exporter(OtlpProtocol::Http, OtlpProtocol::Http.default_endpoint()),
+            SpanExporterBuilder::Http(_)
+        ));
+
+        // The default endpoint follows the protocol
+        let args = Args::parse_from([
+            "rollup-boost",
+            "--l2-jwt-token",
+            SECRET,
+            "--builder-jwt-token",
+            SECRET,
+            "--otlp-protocol",
+            "http",
+        ]);
+        assert_eq!(args.otlp_endpoint(), "http://localhost:4318/v1/traces");
+    }
+
+    #[test]
+    fn test_trace_sampler() {
+        use opentelemetry::trace::{
+            SamplingDecision, SpanContext, SpanId, SpanKind, TraceContextExt, TraceFlags, TraceId,
+            TraceState,
+        };
+        use opentelemetry_sdk::trace::ShouldSample;
+
+        let decision = |sampler: &Sampler, parent: Option<TraceFlags>| {
+            let parent = parent.map(|flags| {
+                opentelemetry::Context::new().with_remote_span_context(SpanContext::new(
+                    TraceId::from_u128(1),
+                    SpanId::from_u64(1),
+                    flags,
+                    true,
+                    TraceState::default(),
+                ))
+            });
+            sampler
+                .should_sample(
+                    parent.as_ref(),
+                    TraceId::from_u128(1),
+                    "forward_request",
+                    &SpanKind::Internal,
+                    &[],
+                    &[],
+                )
+                .decision
+        };
+
+        assert!(matches!(trace_sampler(0.25, false), Sampler::TraceIdRatioBased(r) if r == 0.25));
+        assert_eq!(
+            decision(&trace_sampler(1.0, false), None),
+            SamplingDecision::RecordAndSample
+        );
+        assert_eq!(
+            decision(&trace_sampler(0.0, false), Some(TraceFlags::SAMPLED)),
+            SamplingDecision::Drop
+        );
+
+        // A parent based sampler follows the caller and samples new traces by ratio
+        let sampler = trace_sampler(0.0, true);
+        assert_eq!(
+            decision(&sampler, Some(TraceFlags::SAMPLED)),
+            SamplingDecision::RecordAndSample
+        );
+        assert_eq!(
+            decision(&trace_sampler(1.0, true), Some(TraceFlags::default())),
+            SamplingDecision::Drop
+        );
+        assert_eq!(decision(&sampler, None), SamplingDecision::Drop);
+    }
+
+    #[test]
+    fn test_invalid_trace_sampling_ratio() {
+        run_with_args(&["--tracing", "--trace-sampling-ratio", "1.5"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "trace sampling ratio 1.5 is not between 0.0 and 1.0",
+            ));
+    }
+
+    #[test]
+    fn test_invalid_builder_deadline_fraction() {
+        run_with_args(&["--slot-time", "2000", "--builder-deadline-fraction", "0"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "builder deadline fraction 0 must be above 0.0 and at most 1.0",
+            ));
+    }
+
+    #[test]
+    fn test_invalid_otlp_endpoint() {
+        run_with_args(&["--tracing", "--otlp-endpoint", "not-a-uri"])
+            .failure()
+            .stderr(predicate::str::contains("invalid otlp endpoint not-a-uri"));
+        run_with_args(&["--tracing", "--otlp-endpoint", "grpc://localhost:4317"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "otlp endpoint grpc://localhost:4317 must use the http or https scheme",
+            ));
+        run_with_args(&[
+            "--tracing",
+            "--otlp-endpoint",
+            "http://localhost:4318/v1/traces",
+        ])
+        .failure()
+        .stderr(predicate::str::contains(
+            "has a path, which requires --otlp-protocol http",
+        ));
+    }
+
+    #[test]
+    fn test_config_file() {
+        let path = std::env::temp_dir().join("rollup-boost-test-config.toml");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+l2_jwt_token = "{SECRET}"
+l2_url = "http://127.0.0.1:8598"
+builder_jwt_token = "{SECRET}"
+builder_url = "http://127.0.0.1:8599"
+rpc_host = "file host"
+rpc_port = 1234
+"#
+            ),
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        // The file alone provides every argument
+        Command::cargo_bin("rollup-boost")
+            .unwrap()
+            .args(["--config", config])
+            .env_remove("RPC_HOST")
+            .timeout(Duration::from_secs(10))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "invalid rpc address file host:1234",
+            ));
+
+        // Environment variables override the file
+        Command::cargo_bin("rollup-boost")
+            .unwrap()
+            .args(["--config", config])
+            .env("RPC_HOST", "env host")
+            .timeout(Duration::from_secs(10))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "invalid rpc address env host:1234",
+            ));
+
+        // And flags override both
+        Command::cargo_bin("rollup-boost")
+            .unwrap()
+            .args(["--config", config, "--rpc-host", "flag host"])
+            .env("RPC_HOST", "env host")
+            .timeout(Duration::from_secs(10))
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "invalid rpc address flag host:1234",
+            ));
+
+        std::fs::remove_file(path).unwrap();
+    }
 
     #[test]
     fn test_invalid_args() {
@@ -345,8 +2746,15 @@ mod tests {
 
     /// Spawn a new RPC server equipped with a `JwtLayer` auth middleware.
     async fn spawn_server() -> ServerHandle {
-        let secret = JwtSecret::from_hex(SECRET).unwrap();
-        let addr = format!("{AUTH_ADDR}:{AUTH_PORT}");
+        spawn_server_with(
+            JwtSecret::from_hex(SECRET).unwrap(),
+            &format!("{AUTH_ADDR}:{AUTH_PORT}"),
+        )
+        .await
+    }
+
+    /// Like [spawn_server], validating tokens signed with `secret` on `addr`.
+    async fn spawn_server_with(secret: JwtSecret, addr: &str) -> ServerHandle {
         let validator = JwtAuthValidator::new(secret);
         let layer = AuthLayer::new(validator);
         let middleware = tower::ServiceBuilder::default().layer(layer);
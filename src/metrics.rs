@@ -1,5 +1,44 @@
-use metrics::Counter;
+use crate::engine::EngineMethod;
+use crate::server::PayloadDiff;
+use http::Uri;
+use metrics::{counter, gauge, histogram, Counter, Gauge};
 use metrics_derive::Metrics;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+/// Histogram buckets in seconds for upstream forwards, engine API calls take from a few
+/// milliseconds to a couple of seconds when building a block
+pub const FORWARD_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0,
+];
+
+/// Histogram buckets in seconds for the build latency, from the forkchoice update starting a
+/// payload job to its payload being returned, around the block time
+pub const BUILD_LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 1.5, 2.0, 3.0, 5.0, 10.0];
+
+/// Version of rollup-boost, reported by the `build_info` gauge
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Git commit rollup-boost was built from, `unknown` if it could not be found
+pub const GIT_SHA: &str = env!("GIT_SHA");
+
+/// Reports the version being run and when the process started, for dashboards to join on
+pub fn record_build_info(start_time: SystemTime) {
+    gauge!("build_info", "version" => VERSION, "git_sha" => GIT_SHA).set(1.0);
+    let start_time = start_time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    gauge!("start_time_seconds").set(start_time.as_secs_f64());
+}
+
+/// Records a canary call to `upstream`, `outcome` is one of `success`, `error` or `timeout`.
+/// The duration is only recorded for calls that succeeded
+pub fn record_canary(upstream: &'static str, outcome: &'static str, duration: Duration) {
+    counter!("canary_requests_total", "upstream" => upstream, "outcome" => outcome).increment(1);
+    if outcome == "success" {
+        histogram!("canary_duration_seconds", "upstream" => upstream)
+            .record(duration.as_secs_f64());
+    }
+}
 
 #[derive(Metrics)]
 #[metrics(scope = "rpc")]
@@ -13,3 +52,306 @@ pub struct ServerMetrics {
     #[metric(describe = "Count of get_payload_v3 calls proxied to the builder")]
     pub get_payload_count: Counter,
 }
+
+impl ServerMetrics {
+    /// Counts a payload returned by get_payload_v3, `source` is `builder` or `local`
+    pub fn record_payload_source(&self, source: &'static str) {
+        counter!("payloads_total", "source" => source).increment(1);
+        // Deprecated alias of `payloads_total`, still counted for the dashboards built on it.
+        // It will be removed in 0.2.0
+        counter!("payload_source", "source" => source).increment(1);
+    }
+
+    /// Counts a get_payload call, `retry` when the payload id was answered before
+    pub fn record_get_payload(&self, retry: bool) {
+        counter!("get_payload_calls_total", "retry" => if retry { "true" } else { "false" })
+            .increment(1);
+    }
+
+    /// Reports the share of the last returned payloads that came from the builder
+    pub fn record_builder_win_rate(&self, win_rate: f64) {
+        gauge!("builder_win_rate").set(win_rate);
+    }
+
+    /// Reports by how many wei the builder payload's value exceeds the local payload's
+    pub fn record_builder_value_delta(&self, delta: f64) {
+        gauge!("builder_value_delta").set(delta);
+    }
+
+    /// Records how many more transactions and wei the builder payload has than the local one
+    pub fn record_payload_diff(&self, diff: &PayloadDiff) {
+        histogram!("payload_txcount_delta").record(diff.tx_count_delta as f64);
+        histogram!("payload_value_delta").record(diff.value_delta);
+    }
+
+    /// Records the time from the forkchoice update starting a payload job to its payload being
+    /// returned
+    pub fn record_build_latency(&self, latency: Duration) {
+        histogram!("build_latency_seconds").record(latency.as_secs_f64());
+    }
+
+    /// Counts a builder getPayload response that did not deserialize into a payload
+    pub fn record_builder_malformed(&self) {
+        counter!("builder_malformed_total").increment(1);
+    }
+
+    /// Counts a request rejected for its JWT, `reason` is one of `missing`, `malformed`,
+    /// `invalid_signature` or `expired`
+    pub fn record_auth_failure(&self, reason: &'static str) {
+        counter!("auth_failures_total", "reason" => reason).increment(1);
+    }
+
+    /// Counts a builder payload discarded because the l2 did not report it `VALID`
+    pub fn record_builder_payload_rejected(&self, status: &'static str) {
+        counter!("builder_payload_rejected", "status" => status).increment(1);
+    }
+
+    /// Reports whether the builder answered the last forkchoice update sent to it with `SYNCING`
+    pub fn record_builder_syncing(&self, syncing: bool) {
+        gauge!("builder_syncing").set(if syncing { 1.0 } else { 0.0 });
+    }
+
+    /// Counts a local payload returned in shadow mode where the builder payload would have been
+    pub fn record_shadow_would_have_used_builder(&self) {
+        counter!("shadow_would_have_used_builder_total").increment(1);
+    }
+}
+
+/// Methods the proxy metrics are labelled with, besides the engine methods rollup-boost selects
+/// payloads for and the configured ones
+const KNOWN_METHODS: &[&str] = &[
+    "admin_rollupBoostConfig",
+    "admin_setBuilderEnabled",
+    "debug_traceBlockByHash",
+    "debug_traceBlockByNumber",
+    "debug_traceCall",
+    "debug_traceTransaction",
+    "engine_exchangeCapabilities",
+    "engine_getBlobsV1",
+    "engine_getClientVersionV1",
+    "engine_getPayloadBodiesByHashV1",
+    "engine_getPayloadBodiesByRangeV1",
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getBlockReceipts",
+    "eth_getBlockTransactionCountByHash",
+    "eth_getBlockTransactionCountByNumber",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_getProof",
+    "eth_getStorageAt",
+    "eth_getTransactionByBlockHashAndIndex",
+    "eth_getTransactionByBlockNumberAndIndex",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_getTransactionReceipt",
+    "eth_maxPriorityFeePerGas",
+    "eth_sendRawTransaction",
+    "eth_subscribe",
+    "eth_syncing",
+    "eth_unsubscribe",
+    "miner_setExtra",
+    "miner_setGasLimit",
+    "miner_setGasPrice",
+    "miner_setMaxDASize",
+    "net_listening",
+    "net_peerCount",
+    "net_version",
+    "txpool_content",
+    "txpool_status",
+    "web3_clientVersion",
+];
+
+/// Metrics recorded by the proxy, labelled by method and upstream.
+///
+/// Methods are recorded under their own name when they are known or configured, and as
+/// `other` otherwise so clients cannot add a series for every method they make up. Batches,
+/// whose methods are joined by commas, are recorded as `batch`.
+#[derive(Debug, Default)]
+pub struct ProxyMetrics {
+    methods: HashSet<String>,
+}
+
+impl ProxyMetrics {
+    /// Labels the metrics of `methods` with their name too, e.g. the prefixes given to the method
+    /// routing options when they are whole method names.
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = String>) -> Self {
+        self.methods.extend(methods);
+        self
+    }
+
+    /// Returns the label the calls to `method` are recorded under.
+    fn method_label<'a>(&self, method: &'a str) -> &'a str {
+        if method.contains(',') {
+            "batch"
+        } else if EngineMethod::parse(method).is_some()
+            || KNOWN_METHODS.contains(&method)
+            || self.methods.contains(method)
+        {
+            method
+        } else {
+            "other"
+        }
+    }
+
+    /// Reports the state of a builder's circuit breaker (0 closed, 1 half-open, 2 open)
+    pub fn builder_circuit_state(&self, url: &Uri) -> Gauge {
+        gauge!("builder_circuit_state", "url" => url.to_string())
+    }
+
+    /// Counts a call received by the proxy
+    pub fn record_request(&self, method: &str) {
+        counter!("requests_total", "method" => self.method_label(method).to_string()).increment(1);
+    }
+
+    /// Counts a call rejected for exceeding the rate limit
+    pub fn record_rate_limited(&self, method: &str) {
+        counter!("rate_limited_total", "method" => self.method_label(method).to_string())
+            .increment(1);
+    }
+
+    /// Counts a call not mirrored to a builder because too many forwards were running
+    pub fn record_builder_forward_dropped(&self, method: &str) {
+        counter!(
+            "builder_forwards_dropped_total",
+            "method" => self.method_label(method).to_string()
+        )
+        .increment(1);
+    }
+
+    /// Counts an `eth_sendRawTransaction` that a builder and the l2 answered differently
+    pub fn record_sendrawtx_divergence(&self) {
+        counter!("sendrawtx_divergence_total").increment(1);
+    }
+
+    /// Records the time a builder took to answer a call mirrored to it in the background,
+    /// retries included, or until the forward was cancelled
+    pub fn record_builder_forward_duration(&self, method: &str, duration: Duration) {
+        histogram!(
+            "builder_forward_duration_seconds",
+            "method" => self.method_label(method).to_string()
+        )
+        .record(duration.as_secs_f64());
+    }
+
+    /// Counts a call mirrored to a builder that failed, `outcome` is `error` when it got no
+    /// response, `status` when it got a server error and `timeout` when it was cancelled
+    pub fn record_builder_forward_error(&self, method: &str, outcome: &'static str) {
+        counter!(
+            "builder_forward_errors_total",
+            "method" => self.method_label(method).to_string(),
+            "outcome" => outcome
+        )
+        .increment(1);
+    }
+
+    /// Counts a forwarded call that got no response from an upstream
+    pub fn record_upstream_error(&self, method: &str, upstream: &'static str) {
+        counter!(
+            "upstream_errors_total",
+            "method" => self.method_label(method).to_string(),
+            "upstream" => upstream
+        )
+        .increment(1);
+    }
+
+    /// Records how long an upstream took to answer a forwarded call, `outcome` is one of
+    /// `success`, `status` when it answered with a server error, `error` or `timeout`
+    pub fn record_forward_duration(
+        &self,
+        method: &str,
+        upstream: &'static str,
+        outcome: &'static str,
+        duration: Duration,
+    ) {
+        histogram!(
+            "forward_duration_seconds",
+            "method" => self.method_label(method).to_string(),
+            "upstream" => upstream,
+            "outcome" => outcome
+        )
+        .record(duration.as_secs_f64());
+    }
+
+    /// Reports whether requests forwarded to the l2 go to the failover l2 (1) or the l2 (0)
+    pub fn record_l2_failover_active(&self, active: bool) {
+        gauge!("l2_failover_active").set(if active { 1.0 } else { 0.0 });
+    }
+
+    /// Tracks a request being handled by the proxy until the returned guard is dropped
+    pub fn track_request(&self) -> InflightGuard {
+        InflightGuard::new(gauge!("inflight_requests"))
+    }
+
+    /// Tracks a call being forwarded to an upstream until the returned guard is dropped
+    pub fn track_forward(&self, upstream: &'static str) -> InflightGuard {
+        InflightGuard::new(gauge!("inflight_forwards", "upstream" => upstream))
+    }
+
+    /// Tracks a connection open to an upstream, idle or not, until the returned guard is dropped
+    pub fn track_upstream_connection(&self, upstream: &'static str) -> InflightGuard {
+        InflightGuard::new(gauge!("upstream_connections", "upstream" => upstream))
+    }
+}
+
+/// Increments a gauge on creation and decrements it on drop.
+pub struct InflightGuard(Gauge);
+
+impl InflightGuard {
+    fn new(gauge: Gauge) -> Self {
+        gauge.increment(1.0);
+        Self(gauge)
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.0.decrement(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use metrics_util::layers::{Layer, PrefixLayer};
+
+    #[test]
+    fn test_build_info() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let recorder = PrefixLayer::new("rollup-boost").layer(recorder);
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        record_build_info(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let rendered = handle.render();
+        assert!(rendered.contains(&format!(
+            r#"rollup_boost_build_info{{version="{}",git_sha="{GIT_SHA}"}} 1"#,
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(rendered
+            .lines()
+            .any(|line| line == "rollup_boost_start_time_seconds 1700000000"));
+    }
+
+    #[test]
+    fn test_method_label() {
+        let metrics = ProxyMetrics::default().with_methods(["mock_method".to_string()]);
+        assert_eq!(metrics.method_label("eth_call"), "eth_call");
+        assert_eq!(
+            metrics.method_label("engine_getPayloadV4"),
+            "engine_getPayloadV4"
+        );
+        assert_eq!(metrics.method_label("mock_method"), "mock_method");
+        assert_eq!(metrics.method_label("eth_madeUp1234"), "other");
+        assert_eq!(metrics.method_label("mock_method2"), "other");
+        assert_eq!(metrics.method_label("eth_call,eth_chainId"), "batch");
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Rate at which a [TokenBucket] lets requests through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Tokens added to the bucket every second
+    pub requests_per_second: u32,
+    /// Tokens the bucket holds at most, i.e. the requests accepted at once after an idle period
+    pub burst: u32,
+}
+
+#[derive(Debug)]
+struct Inner {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Lets `requests_per_second` requests through on average, with bursts of up to `burst`
+/// requests. The bucket starts full.
+#[derive(Debug)]
+pub struct TokenBucket {
+    config: RateLimitConfig,
+    inner: Mutex<Inner>,
+}
+
+impl TokenBucket {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                tokens: f64::from(config.burst),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token, returning false if the bucket is empty.
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let elapsed = now.saturating_duration_since(inner.refilled_at);
+        inner.tokens = (inner.tokens
+            + elapsed.as_secs_f64() * f64::from(self.config.requests_per_second))
+        .min(f64::from(self.config.burst));
+        inner.refilled_at = now;
+
+        if inner.tokens >= 1.0 {
+            inner.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Limits the calls accepted by the proxy.
+///
+/// Engine API calls draw from their own bucket so other traffic cannot throttle block
+/// production, and are not limited at all when no engine bucket is configured.
+#[derive(Debug)]
+pub struct RateLimiter {
+    default: Option<TokenBucket>,
+    engine: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(default: Option<RateLimitConfig>, engine: Option<RateLimitConfig>) -> Self {
+        Self {
+            default: default.map(TokenBucket::new),
+            engine: engine.map(TokenBucket::new),
+        }
+    }
+
+    /// Takes a token for a call to `method`, returning false if the call exceeds the limit.
+    pub fn try_acquire(&self, method: &str) -> bool {
+        let bucket = if method.starts_with("engine_") {
+            &self.engine
+        } else {
+            &self.default
+        };
+        bucket.as_ref().map_or(true, TokenBucket::try_acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket() {
+        let bucket = TokenBucket::new(RateLimitConfig {
+            requests_per_second: 10,
+            burst: 2,
+        });
+        let start = bucket.inner.lock().unwrap().refilled_at;
+
+        assert!(bucket.try_acquire_at(start));
+        assert!(bucket.try_acquire_at(start));
+        assert!(!bucket.try_acquire_at(start));
+
+        // A token is added every 100ms
+        assert!(!bucket.try_acquire_at(start + Duration::from_millis(50)));
+        assert!(bucket.try_acquire_at(start + Duration::from_millis(100)));
+        assert!(!bucket.try_acquire_at(start + Duration::from_millis(100)));
+
+        // The bucket never holds more than the burst
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.try_acquire_at(later));
+        assert!(bucket.try_acquire_at(later));
+        assert!(!bucket.try_acquire_at(later));
+    }
+
+    #[test]
+    fn test_engine_methods() {
+        let config = RateLimitConfig {
+            requests_per_second: 1,
+            burst: 1,
+        };
+
+        let limiter = RateLimiter::new(Some(config), None);
+        assert!(limiter.try_acquire("eth_chainId"));
+        assert!(!limiter.try_acquire("eth_chainId"));
+        for _ in 0..10 {
+            assert!(limiter.try_acquire("engine_getPayloadV3"));
+        }
+
+        let limiter = RateLimiter::new(
+            Some(config),
+            Some(RateLimitConfig {
+                requests_per_second: 1,
+                burst: 2,
+            }),
+        );
+        assert!(limiter.try_acquire("eth_chainId"));
+        assert!(!limiter.try_acquire("eth_chainId"));
+        assert!(limiter.try_acquire("engine_getPayloadV3"));
+        assert!(limiter.try_acquire("engine_newPayloadV3"));
+        assert!(!limiter.try_acquire("engine_forkchoiceUpdatedV3"));
+    }
+}
@@ -0,0 +1,175 @@
+use http_body_util::BodyExt;
+use jsonrpsee::core::BoxError;
+use jsonrpsee::http_client::{HttpBody, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// A request handled by the proxy and the response returned for it, one line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// JSON-RPC call or batch sent by the client
+    pub request: serde_json::Value,
+    /// HTTP status of the response
+    pub status: u16,
+    /// JSON-RPC response or batch returned to the client, or the raw body as a string if it is
+    /// not JSON
+    pub response: serde_json::Value,
+}
+
+impl Exchange {
+    fn new(request: &[u8], status: u16, response: &[u8]) -> Self {
+        Self {
+            request: parse_body(request),
+            status,
+            response: parse_body(response),
+        }
+    }
+}
+
+fn parse_body(body: &[u8]) -> serde_json::Value {
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+/// Appends every exchange of the proxy to a JSONL file, so a session can be replayed against
+/// mock upstreams.
+#[derive(Debug)]
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    /// Opens `path` for appending, creating it if it does not exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records `request` with the response returned for it, then returns the response.
+    ///
+    /// The response body is buffered to be recorded. A failure to write the recording is logged
+    /// and does not fail the request.
+    pub async fn record(
+        &self,
+        request: &[u8],
+        response: HttpResponse,
+    ) -> Result<HttpResponse, BoxError> {
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await?.to_bytes();
+
+        let exchange = Exchange::new(request, parts.status.as_u16(), &body);
+        if let Err(e) = self.write(&exchange) {
+            warn!(message = "failed to record exchange", error = %e);
+        }
+        Ok(HttpResponse::from_parts(
+            parts,
+            HttpBody::from(body.to_vec()),
+        ))
+    }
+
+    fn write(&self, exchange: &Exchange) -> io::Result<()> {
+        let mut line = serde_json::to_vec(exchange)?;
+        line.push(b'\n');
+        // Written at once so concurrent exchanges never interleave
+        self.file.lock().unwrap().write_all(&line)
+    }
+}
+
+/// Reads the exchanges of a recording, in the order they were recorded.
+#[cfg(test)]
+pub fn read_recording(path: &Path) -> eyre::Result<Vec<Exchange>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Sends the requests of a recording to the proxy at `url` one after the other, returning the
+/// exchanges seen this time. Comparing them with the recording checks that the same upstream
+/// responses lead to the same routing and payload selection.
+#[cfg(test)]
+pub async fn replay(recording: &[Exchange], url: &str) -> eyre::Result<Vec<Exchange>> {
+    use http::header::CONTENT_TYPE;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+
+    let client: Client<_, HttpBody> = Client::builder(TokioExecutor::new()).build_http();
+    let mut replayed = vec![];
+    for exchange in recording {
+        let request = serde_json::to_vec(&exchange.request)?;
+        let req = http::Request::post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(HttpBody::from(request.clone()))?;
+
+        let (parts, body) = client.request(req).await?.into_parts();
+        let body = body.collect().await?.to_bytes();
+        replayed.push(Exchange::new(&request, parts.status.as_u16(), &body));
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recorder_appends_exchanges() -> eyre::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("rollup-boost-record-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = Recorder::open(&path)?;
+        let request = br#"{"jsonrpc":"2.0","method":"eth_chainId","id":1}"#;
+        let response = http::Response::builder()
+            .status(401)
+            .body(HttpBody::from("unauthorized"))?;
+        let response = recorder.record(request, response).await?;
+
+        // The response is returned unchanged
+        assert_eq!(response.status(), 401);
+        let body = response.into_body().collect().await?.to_bytes();
+        assert_eq!(body.as_ref(), b"unauthorized");
+
+        let response =
+            http::Response::new(HttpBody::from(r#"{"jsonrpc":"2.0","result":"0x1","id":1}"#));
+        recorder.record(request, response).await?;
+
+        // Reopening appends to the recording
+        drop(recorder);
+        let recorder = Recorder::open(&path)?;
+        recorder
+            .record(b"not json", http::Response::new(HttpBody::empty()))
+            .await?;
+
+        let recording = read_recording(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            recording,
+            vec![
+                Exchange {
+                    request: serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "id": 1}),
+                    status: 401,
+                    response: "unauthorized".into(),
+                },
+                Exchange {
+                    request: serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "id": 1}),
+                    status: 200,
+                    response: serde_json::json!({"jsonrpc": "2.0", "result": "0x1", "id": 1}),
+                },
+                Exchange {
+                    request: "not json".into(),
+                    status: 200,
+                    response: "".into(),
+                },
+            ]
+        );
+        Ok(())
+    }
+}
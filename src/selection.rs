@@ -0,0 +1,168 @@
+use alloy_primitives::U256;
+use clap::ValueEnum;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// What a selector knows about a payload returned by get_payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// Value of the block to the fee recipient, in wei
+    pub block_value: U256,
+}
+
+/// The payload picked by a [PayloadSelector].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Local,
+    Builder,
+}
+
+/// Decides which of the local and builder payloads get_payload returns.
+///
+/// Only called when both payloads were returned, the builder's having been validated by the l2.
+/// When either is missing the other one is returned whatever the selector.
+pub trait PayloadSelector: Debug + Send + Sync {
+    fn select(&self, local: &Candidate, builder: &Candidate) -> Selection;
+}
+
+/// Always returns the local payload.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysLocal;
+
+impl PayloadSelector for AlwaysLocal {
+    fn select(&self, _local: &Candidate, _builder: &Candidate) -> Selection {
+        Selection::Local
+    }
+}
+
+/// Returns the builder payload whenever it is valid, whatever its value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysBuilderIfValid;
+
+impl PayloadSelector for AlwaysBuilderIfValid {
+    fn select(&self, _local: &Candidate, _builder: &Candidate) -> Selection {
+        Selection::Builder
+    }
+}
+
+/// Returns the builder payload when it is worth at least `min_delta` more than the local one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HigherValue {
+    min_delta: U256,
+}
+
+impl HigherValue {
+    pub fn new(min_delta: U256) -> Self {
+        Self { min_delta }
+    }
+}
+
+impl PayloadSelector for HigherValue {
+    fn select(&self, local: &Candidate, builder: &Candidate) -> Selection {
+        if builder.block_value >= local.block_value.saturating_add(self.min_delta) {
+            Selection::Builder
+        } else {
+            Selection::Local
+        }
+    }
+}
+
+/// The built-in selectors, picked with `--selection-strategy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SelectionStrategy {
+    /// The builder payload when it is worth at least the minimum value delta more
+    HigherValue,
+    /// The builder payload whenever it is valid
+    AlwaysBuilder,
+    /// The local payload
+    AlwaysLocal,
+}
+
+impl SelectionStrategy {
+    /// Returns the selector of the strategy, `min_delta` being used by `higher-value` only.
+    pub fn selector(self, min_delta: U256) -> Arc<dyn PayloadSelector> {
+        match self {
+            SelectionStrategy::HigherValue => Arc::new(HigherValue::new(min_delta)),
+            SelectionStrategy::AlwaysBuilder => Arc::new(AlwaysBuilderIfValid),
+            SelectionStrategy::AlwaysLocal => Arc::new(AlwaysLocal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(block_value: u64) -> Candidate {
+        Candidate {
+            block_value: U256::from(block_value),
+        }
+    }
+
+    #[test]
+    fn test_always_local() {
+        for (local, builder) in [(10, 5), (10, 10), (10, 100)] {
+            assert_eq!(
+                AlwaysLocal.select(&candidate(local), &candidate(builder)),
+                Selection::Local
+            );
+        }
+    }
+
+    #[test]
+    fn test_always_builder_if_valid() {
+        for (local, builder) in [(10, 5), (10, 10), (10, 100)] {
+            assert_eq!(
+                AlwaysBuilderIfValid.select(&candidate(local), &candidate(builder)),
+                Selection::Builder
+            );
+        }
+    }
+
+    #[test]
+    fn test_higher_value() {
+        let selector = HigherValue::default();
+        assert_eq!(
+            selector.select(&candidate(10), &candidate(5)),
+            Selection::Local
+        );
+        // Ties go to the builder
+        assert_eq!(
+            selector.select(&candidate(10), &candidate(10)),
+            Selection::Builder
+        );
+
+        let selector = HigherValue::new(U256::from(10));
+        assert_eq!(
+            selector.select(&candidate(10), &candidate(15)),
+            Selection::Local
+        );
+        assert_eq!(
+            selector.select(&candidate(10), &candidate(20)),
+            Selection::Builder
+        );
+        // The delta does not overflow next to the largest values
+        assert_eq!(
+            selector.select(
+                &Candidate {
+                    block_value: U256::MAX
+                },
+                &Candidate {
+                    block_value: U256::MAX
+                }
+            ),
+            Selection::Builder
+        );
+    }
+
+    #[test]
+    fn test_selection_strategy() {
+        let (local, builder) = (candidate(10), candidate(15));
+        let select = |strategy: SelectionStrategy| {
+            strategy.selector(U256::from(10)).select(&local, &builder)
+        };
+        assert_eq!(select(SelectionStrategy::HigherValue), Selection::Local);
+        assert_eq!(select(SelectionStrategy::AlwaysBuilder), Selection::Builder);
+        assert_eq!(select(SelectionStrategy::AlwaysLocal), Selection::Local);
+    }
+}
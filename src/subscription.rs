@@ -0,0 +1,259 @@
+use crate::auth::SharedSecret;
+use crate::proxy::SERVER_ERROR_CODE;
+use futures::StreamExt;
+use http::header::AUTHORIZATION;
+use http::uri::{PathAndQuery, Scheme};
+use http::{HeaderMap, Uri};
+use jsonrpsee::core::client::{Subscription, SubscriptionClientT};
+use jsonrpsee::core::params::ArrayParams;
+use jsonrpsee::core::{ClientError, RegisterMethodError, SubscriptionResult};
+use jsonrpsee::types::{ErrorObject, ErrorObjectOwned};
+use jsonrpsee::ws_client::{WsClient, WsClientBuilder};
+use jsonrpsee::{RpcModule, SubscriptionMessage, SubscriptionSink};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+/// Serves `eth_subscribe` and `eth_unsubscribe` to WebSocket clients by subscribing to the l2
+/// over a WebSocket connection shared by every subscription, and relaying its notifications.
+#[derive(Debug)]
+pub struct SubscriptionProxy {
+    l2_uri: Uri,
+    secret: SharedSecret,
+    /// Opened on the first subscription and opened again once it drops
+    client: Mutex<Option<Arc<WsClient>>>,
+}
+
+impl SubscriptionProxy {
+    /// Proxies subscriptions to the l2 at `l2_uri`, reached with the `ws` scheme or `wss` when the
+    /// uri uses `https`.
+    pub fn new(l2_uri: &Uri, secret: impl Into<SharedSecret>) -> Self {
+        Self {
+            l2_uri: ws_uri(l2_uri),
+            secret: secret.into(),
+            client: Mutex::new(None),
+        }
+    }
+
+    /// Returns the connection to the l2, connecting if it is not open.
+    async fn client(&self) -> Result<Arc<WsClient>, ClientError> {
+        let mut client = self.client.lock().await;
+        if let Some(client) = client.as_ref().filter(|client| client.is_connected()) {
+            return Ok(client.clone());
+        }
+
+        // The token is only checked during the handshake, so it does not need refreshing
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, self.secret.bearer_header());
+        let connected = Arc::new(
+            WsClientBuilder::default()
+                .set_headers(headers)
+                .build(self.l2_uri.to_string())
+                .await?,
+        );
+        info!(message = "connected to l2 for subscriptions", url = %self.l2_uri);
+        *client = Some(connected.clone());
+        Ok(connected)
+    }
+
+    async fn subscribe(
+        &self,
+        params: Vec<serde_json::Value>,
+    ) -> Result<Subscription<serde_json::Value>, ClientError> {
+        let mut array = ArrayParams::new();
+        for param in params {
+            array.insert(param)?;
+        }
+        self.client()
+            .await?
+            .subscribe("eth_subscribe", array, "eth_unsubscribe")
+            .await
+    }
+}
+
+impl TryInto<RpcModule<()>> for SubscriptionProxy {
+    type Error = RegisterMethodError;
+
+    fn try_into(self) -> Result<RpcModule<()>, Self::Error> {
+        let mut subscriptions = RpcModule::new(self);
+        subscriptions.register_subscription(
+            "eth_subscribe",
+            "eth_subscription",
+            "eth_unsubscribe",
+            |params, pending, proxy, _| async move {
+                let params = match params.parse::<Vec<serde_json::Value>>() {
+                    Ok(params) => params,
+                    Err(e) => {
+                        pending.reject(e).await;
+                        return Ok(());
+                    }
+                };
+
+                // Subscribed before accepting, so an error from the l2 reaches the client
+                match proxy.subscribe(params).await {
+                    Ok(subscription) => relay(subscription, pending.accept().await?).await,
+                    Err(e) => {
+                        debug!(message = "failed to subscribe to l2", error = %e);
+                        pending.reject(subscription_error(e)).await;
+                        Ok(())
+                    }
+                }
+            },
+        )?;
+
+        let mut module = RpcModule::new(());
+        module.merge(subscriptions)?;
+        Ok(module)
+    }
+}
+
+/// Sends the notifications of an l2 subscription to the client until either side closes it.
+/// Dropping `subscription` unsubscribes from the l2.
+async fn relay(
+    mut subscription: Subscription<serde_json::Value>,
+    sink: SubscriptionSink,
+) -> SubscriptionResult {
+    loop {
+        tokio::select! {
+            _ = sink.closed() => return Ok(()),
+            notification = subscription.next() => match notification {
+                Some(Ok(notification)) => {
+                    sink.send(SubscriptionMessage::from_json(&notification)?).await?
+                }
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err("l2 closed the subscription".into()),
+            },
+        }
+    }
+}
+
+fn subscription_error(error: ClientError) -> ErrorObjectOwned {
+    match error {
+        ClientError::Call(error) => error,
+        other => ErrorObject::owned(SERVER_ERROR_CODE, other.to_string(), None::<()>),
+    }
+}
+
+/// Swaps the `http` scheme of an upstream uri for `ws`, and `https` for `wss`.
+fn ws_uri(uri: &Uri) -> Uri {
+    let scheme = if uri.scheme() == Some(&Scheme::HTTPS) {
+        "wss"
+    } else {
+        "ws"
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.scheme = Some(scheme.parse().expect("ws schemes are valid"));
+    if parts.path_and_query.is_none() {
+        parts.path_and_query = Some(PathAndQuery::from_static("/"));
+    }
+    Uri::from_parts(parts).expect("only the scheme of a valid uri is changed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpsee::core::client::ClientT;
+    use jsonrpsee::rpc_params;
+    use jsonrpsee::server::{Server, ServerHandle};
+    use reth_rpc_layer::JwtSecret;
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    /// Spawns an l2 sending a `newHeads` notification every 50ms to each subscriber.
+    async fn spawn_l2() -> eyre::Result<(SocketAddr, ServerHandle)> {
+        let mut module = RpcModule::new(());
+        module.register_subscription(
+            "eth_subscribe",
+            "eth_subscription",
+            "eth_unsubscribe",
+            |params, pending, _, _| async move {
+                if params.one::<String>().ok().as_deref() != Some("newHeads") {
+                    pending
+                        .reject(ErrorObject::owned(
+                            -32602,
+                            "unsupported subscription",
+                            None::<()>,
+                        ))
+                        .await;
+                    return Ok(());
+                }
+
+                let sink = pending.accept().await?;
+                for number in 1u64.. {
+                    let header = json!({ "number": format!("{number:#x}") });
+                    sink.send(SubscriptionMessage::from_json(&header)?).await?;
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                Ok(())
+            },
+        )?;
+
+        let server = Server::builder().build("127.0.0.1:0").await?;
+        let addr = server.local_addr()?;
+        Ok((addr, server.start(module)))
+    }
+
+    /// Spawns a WebSocket server proxying subscriptions to the l2 at `l2_addr`.
+    async fn spawn_proxy(l2_addr: SocketAddr) -> eyre::Result<(SocketAddr, ServerHandle)> {
+        let module: RpcModule<()> =
+            SubscriptionProxy::new(&format!("http://{l2_addr}").parse()?, JwtSecret::random())
+                .try_into()?;
+
+        let server = Server::builder().ws_only().build("127.0.0.1:0").await?;
+        let addr = server.local_addr()?;
+        Ok((addr, server.start(module)))
+    }
+
+    #[test]
+    fn test_ws_uri() {
+        let uri = |uri: &str| ws_uri(&uri.parse().unwrap()).to_string();
+        assert_eq!(uri("http://localhost:8551"), "ws://localhost:8551/");
+        assert_eq!(
+            uri("https://l2.example.com/auth"),
+            "wss://l2.example.com/auth"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_heads_subscription() -> eyre::Result<()> {
+        let (l2_addr, _l2) = spawn_l2().await?;
+        let (proxy_addr, _proxy) = spawn_proxy(l2_addr).await?;
+        let client = WsClientBuilder::default()
+            .build(format!("ws://{proxy_addr}"))
+            .await?;
+
+        let mut subscription: Subscription<serde_json::Value> = client
+            .subscribe("eth_subscribe", rpc_params!["newHeads"], "eth_unsubscribe")
+            .await?;
+        for number in ["0x1", "0x2"] {
+            let header = tokio::time::timeout(Duration::from_secs(5), subscription.next())
+                .await?
+                .expect("subscription is open")?;
+            assert_eq!(header["number"], number);
+        }
+        subscription.unsubscribe().await?;
+
+        // Errors from the l2 are returned to the client
+        let error = client
+            .subscribe::<serde_json::Value, _>(
+                "eth_subscribe",
+                rpc_params!["logs"],
+                "eth_unsubscribe",
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, ClientError::Call(ref error) if error.message() == "unsupported subscription"),
+            "{error:?}"
+        );
+
+        // Only subscriptions are served over WebSocket
+        assert!(client
+            .request::<serde_json::Value, _>("eth_chainId", rpc_params![])
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
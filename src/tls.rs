@@ -0,0 +1,463 @@
+use crate::proxy::UpstreamHttpVersion;
+use clap::{arg, Parser};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioIo;
+use jsonrpsee::core::BoxError;
+use jsonrpsee::http_client::{HttpBody, HttpRequest, HttpResponse};
+use jsonrpsee::server::StopHandle;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, error, warn};
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("no private key found in {0}")]
+    MissingPrivateKey(PathBuf),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error("invalid client CA: {0}")]
+    ClientCa(#[from] VerifierBuilderError),
+}
+
+/// TLS settings for connections to `https` upstreams.
+#[derive(Parser, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsArgs {
+    /// PEM bundle of the CA certificates trusted for https upstreams, replacing the webpki roots
+    #[arg(long, env, value_name = "PATH")]
+    pub tls_ca_bundle: Option<PathBuf>,
+
+    /// PEM client certificate presented to https upstreams for mutual TLS
+    #[arg(long, env, value_name = "PATH", requires = "tls_client_key")]
+    pub tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate
+    #[arg(long, env, value_name = "PATH", requires = "tls_client_cert")]
+    pub tls_client_key: Option<PathBuf>,
+}
+
+/// Builds a connector that negotiates TLS with `https` upstreams and connects to `http`
+/// upstreams in plain text, opening TCP connections with `http`. Only `http_version` is offered
+/// with ALPN, so an upstream that does not speak it fails the handshake rather than switching
+/// the client to another version.
+pub fn https_connector(
+    args: &TlsArgs,
+    http: HttpConnector,
+    http_version: UpstreamHttpVersion,
+) -> Result<HttpsConnector<HttpConnector>, TlsError> {
+    let mut roots = RootCertStore::empty();
+    match &args.tls_ca_bundle {
+        Some(path) => {
+            for cert in read_certs(path)? {
+                roots.add(cert)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+    let config = match (&args.tls_client_cert, &args.tls_client_key) {
+        (Some(cert), Some(key)) => {
+            builder.with_client_auth_cert(read_certs(cert)?, read_key(key)?)?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    let builder = HttpsConnectorBuilder::new()
+        .with_tls_config(config)
+        .https_or_http();
+    Ok(match http_version {
+        UpstreamHttpVersion::Http1 => builder.enable_http1().wrap_connector(http),
+        UpstreamHttpVersion::Http2 => builder.enable_http2().wrap_connector(http),
+    })
+}
+
+/// TLS settings of the rpc server, which serves plain HTTP unless given a certificate.
+#[derive(Parser, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpcTlsArgs {
+    /// PEM certificate chain the rpc server terminates TLS with
+    #[arg(long, env, value_name = "PATH", requires = "rpc_tls_key")]
+    pub rpc_tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the rpc server certificate
+    #[arg(long, env, value_name = "PATH", requires = "rpc_tls_cert")]
+    pub rpc_tls_key: Option<PathBuf>,
+
+    /// PEM bundle of the CA certificates client certificates must be signed by. Clients
+    /// presenting no certificate signed by one of them cannot connect
+    #[arg(long, env, value_name = "PATH", requires = "rpc_tls_cert")]
+    pub rpc_client_ca: Option<PathBuf>,
+}
+
+/// Builds the acceptor terminating TLS on the connections to the rpc server, `None` when the
+/// server serves plain HTTP.
+pub fn rpc_tls_acceptor(args: &RpcTlsArgs) -> Result<Option<TlsAcceptor>, TlsError> {
+    let (Some(cert), Some(key)) = (&args.rpc_tls_cert, &args.rpc_tls_key) else {
+        return Ok(None);
+    };
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match &args.rpc_client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots.add(cert)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(read_certs(cert)?, read_key(key)?)?;
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// Time allowed to a client of the rpc server to complete the TLS handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves HTTP over TLS on the connections accepted on `listener` with `service`, the rpc server
+/// with its middleware, until `stop_handle` is stopped. Connections over `max_connections` are
+/// closed right away, and open ones are shut down gracefully once stopped.
+///
+/// Connections whose handshake does not complete within `handshake_timeout` are closed, so
+/// clients that never finish it do not hold the connections of the other ones.
+pub async fn serve<S>(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    service: S,
+    stop_handle: StopHandle,
+    max_connections: u32,
+    handshake_timeout: Duration,
+) where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    let connections = Arc::new(Semaphore::new(max_connections as usize));
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            _ = stop_handle.clone().shutdown() => break,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!(message = "Error accepting rpc connection", error = %e);
+                    continue;
+                }
+            },
+        };
+        let Ok(permit) = connections.clone().try_acquire_owned() else {
+            warn!(message = "too many rpc connections, refusing connection", %remote_addr);
+            continue;
+        };
+
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        let stop_handle = stop_handle.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let handshake = tokio::time::timeout(handshake_timeout, acceptor.accept(stream));
+            let result = tokio::select! {
+                result = handshake => result,
+                _ = stop_handle.clone().shutdown() => return,
+            };
+            // Clients without a certificate signed by the client CA are refused here
+            let stream = match result {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    debug!(message = "TLS handshake with rpc client failed", %remote_addr, error = %e);
+                    return;
+                }
+                Err(_) => {
+                    debug!(message = "TLS handshake with rpc client timed out", %remote_addr, timeout = ?handshake_timeout);
+                    return;
+                }
+            };
+            let service = service_fn(move |req: Request<Incoming>| {
+                let mut service = service.clone();
+                service.call(req.map(HttpBody::new))
+            });
+            let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = stop_handle.shutdown() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(err) = result {
+                debug!(message = "Error serving rpc connection", error = %err);
+            }
+        });
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| TlsError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<_, _>>()
+        .map_err(|source| TlsError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|source| TlsError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?
+        .ok_or_else(|| TlsError::MissingPrivateKey(path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use hyper_util::client::legacy::connect::Connection;
+    use hyper_util::client::legacy::Client;
+    use hyper_util::rt::TokioExecutor;
+    use jsonrpsee::server::{stop_channel, Server};
+    use jsonrpsee::RpcModule;
+
+    /// Writes `pem` to a file of the temporary directory, returning its path.
+    fn write_pem(name: &str, pem: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rollup-boost-{name}-{}.pem", std::process::id()));
+        std::fs::write(&path, pem).unwrap();
+        path
+    }
+
+    /// Calls `greet_melkor` on the rpc server listening on `port`, over TLS set up with `args`.
+    async fn greet(port: u16, args: &TlsArgs) -> eyre::Result<serde_json::Value> {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let client: Client<_, HttpBody> = Client::builder(TokioExecutor::new())
+            .build(https_connector(args, http, UpstreamHttpVersion::Http1)?);
+        let req = http::Request::post(format!("https://localhost:{port}/"))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(HttpBody::from(
+                r#"{"jsonrpc":"2.0","id":1,"method":"greet_melkor","params":[]}"#,
+            ))?;
+        let body = client
+            .request(req)
+            .await?
+            .into_body()
+            .collect()
+            .await?
+            .to_bytes();
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    #[tokio::test]
+    async fn test_https_connector_alpn() -> eyre::Result<()> {
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+        let mut config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![server.cert.der().clone()],
+                    PrivateKeyDer::Pkcs8(server.key_pair.serialize_der().into()),
+                )?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let uri: http::Uri =
+            format!("https://localhost:{}", listener.local_addr()?.port()).parse()?;
+        let upstream = tokio::spawn(async move {
+            let mut streams = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    streams.push(stream);
+                }
+            }
+        });
+
+        let ca_bundle = write_pem("alpn-ca", &server.cert.pem());
+        let args = TlsArgs {
+            tls_ca_bundle: Some(ca_bundle.clone()),
+            ..Default::default()
+        };
+        // The upstream speaks both, so the connector gets the version it offers
+        for (http_version, h2) in [
+            (UpstreamHttpVersion::Http1, false),
+            (UpstreamHttpVersion::Http2, true),
+        ] {
+            let mut http = HttpConnector::new();
+            http.enforce_http(false);
+            let stream = https_connector(&args, http, http_version)?
+                .call(uri.clone())
+                .await
+                .map_err(|e| eyre::eyre!("{e}"))?;
+            assert_eq!(
+                stream.connected().is_negotiated_h2(),
+                h2,
+                "{http_version:?}"
+            );
+        }
+
+        upstream.abort();
+        std::fs::remove_file(ca_bundle)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_client_certificates() -> eyre::Result<()> {
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+        let ca_key = rcgen::KeyPair::generate()?;
+        let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key)?;
+
+        let mut client_params = rcgen::CertificateParams::new(vec!["op-node".to_string()])?;
+        client_params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth];
+        let client_key = rcgen::KeyPair::generate()?;
+        let client = client_params.signed_by(&client_key, &ca, &ca_key)?;
+        // The same certificate, signed by itself rather than the client CA
+        let untrusted_key = rcgen::KeyPair::generate()?;
+        let untrusted = client_params.self_signed(&untrusted_key)?;
+
+        let server_cert = write_pem("rpc-cert", &server.cert.pem());
+        let server_key = write_pem("rpc-key", &server.key_pair.serialize_pem());
+        let client_ca = write_pem("rpc-client-ca", &ca.pem());
+        let client_cert = write_pem("rpc-client-cert", &client.pem());
+        let client_key = write_pem("rpc-client-key", &client_key.serialize_pem());
+        let untrusted_cert = write_pem("rpc-untrusted-cert", &untrusted.pem());
+        let untrusted_key = write_pem("rpc-untrusted-key", &untrusted_key.serialize_pem());
+
+        let acceptor = rpc_tls_acceptor(&RpcTlsArgs {
+            rpc_tls_cert: Some(server_cert.clone()),
+            rpc_tls_key: Some(server_key.clone()),
+            rpc_client_ca: Some(client_ca.clone()),
+        })?
+        .expect("TLS is enabled");
+        let mut module = RpcModule::new(());
+        module.register_method("greet_melkor", |_, _, _| "You are the dark lord")?;
+        let (stop_handle, server_handle) = stop_channel();
+        let service = Server::builder()
+            .to_service_builder()
+            .build(module, stop_handle.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        let server = tokio::spawn(serve(
+            listener,
+            acceptor,
+            service,
+            stop_handle,
+            16,
+            HANDSHAKE_TIMEOUT,
+        ));
+
+        let client_args = |cert: Option<&PathBuf>, key: Option<&PathBuf>| TlsArgs {
+            tls_ca_bundle: Some(server_cert.clone()),
+            tls_client_cert: cert.cloned(),
+            tls_client_key: key.cloned(),
+        };
+        let response = greet(port, &client_args(Some(&client_cert), Some(&client_key))).await?;
+        assert_eq!(response["result"], "You are the dark lord");
+
+        // Clients presenting a certificate the client CA did not sign, or none, cannot connect
+        assert!(greet(
+            port,
+            &client_args(Some(&untrusted_cert), Some(&untrusted_key))
+        )
+        .await
+        .is_err());
+        assert!(greet(port, &client_args(None, None)).await.is_err());
+
+        server_handle.stop()?;
+        server.await?;
+        for path in [
+            server_cert,
+            server_key,
+            client_ca,
+            client_cert,
+            client_key,
+            untrusted_cert,
+            untrusted_key,
+        ] {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stalled_handshake_times_out() -> eyre::Result<()> {
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+        let server_cert = write_pem("stalled-cert", &server.cert.pem());
+        let server_key = write_pem("stalled-key", &server.key_pair.serialize_pem());
+        let acceptor = rpc_tls_acceptor(&RpcTlsArgs {
+            rpc_tls_cert: Some(server_cert.clone()),
+            rpc_tls_key: Some(server_key.clone()),
+            rpc_client_ca: None,
+        })?
+        .expect("TLS is enabled");
+        let mut module = RpcModule::new(());
+        module.register_method("greet_melkor", |_, _, _| "You are the dark lord")?;
+        let (stop_handle, server_handle) = stop_channel();
+        let service = Server::builder()
+            .to_service_builder()
+            .build(module, stop_handle.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        // A single connection, held by the stalled client until its handshake times out
+        let timeout = Duration::from_millis(200);
+        let server = tokio::spawn(serve(listener, acceptor, service, stop_handle, 1, timeout));
+
+        let _stalled = tokio::net::TcpStream::connect(("127.0.0.1", port)).await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let args = TlsArgs {
+            tls_ca_bundle: Some(server_cert.clone()),
+            ..Default::default()
+        };
+        assert!(greet(port, &args).await.is_err());
+
+        tokio::time::sleep(timeout).await;
+        let response = greet(port, &args).await?;
+        assert_eq!(response["result"], "You are the dark lord");
+
+        server_handle.stop()?;
+        server.await?;
+        for path in [server_cert, server_key] {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}